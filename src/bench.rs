@@ -0,0 +1,164 @@
+use crate::*;
+use clap::{ArgEnum, Parser};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Compression runs to time, read from a .toml file with a `[[benchmark]]` table per run (see `data/bench.toml`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BenchSuite {
+    pub benchmark: Vec<Benchmark>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Benchmark {
+    pub name: String,
+    pub file: PathBuf, // input file, relative to where the suite is run from
+    #[serde(default = "default_fmt")]
+    pub fmt: String, // any --fmt of `compress`
+    #[serde(default)]
+    pub args: String, // `compress` options like "-i10 -a3"
+}
+
+fn default_fmt() -> String {
+    "programs-list".to_string()
+}
+
+/// The measurements of one benchmark. With several runs the wall time is the fastest run and the memory the largest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub name: String,
+    pub wall_millis: u64, // loading the input and compressing it
+    pub peak_rss_kb: Option<usize>, // see `peak_rss_kb()`, only available on Linux
+    pub compression_ratio: f64,
+    pub num_abstractions: usize,
+    pub runs: usize,
+}
+
+/// The output of the `bench` binary, which is also what it reads back in as a baseline
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub benchmarks: Vec<BenchResult>,
+}
+
+/// How much worse than the baseline each metric can get before it counts as a regression
+#[derive(Parser, Debug, Serialize, Clone)]
+pub struct BenchThresholds {
+    /// allowed increase in wall time, as a fraction of the baseline's
+    #[clap(long, default_value = "0.25")]
+    pub max_time_increase: f64,
+
+    /// wall time increases of at most this many milliseconds are never regressions, since small benchmarks are noisy
+    #[clap(long, default_value = "50")]
+    pub time_slack_millis: u64,
+
+    /// allowed increase in peak memory, as a fraction of the baseline's
+    #[clap(long, default_value = "0.25")]
+    pub max_memory_increase: f64,
+
+    /// allowed decrease in compression ratio, as a fraction of the baseline's
+    #[clap(long, default_value = "0.001")]
+    pub max_ratio_decrease: f64,
+}
+
+impl Default for BenchThresholds {
+    fn default() -> Self {
+        BenchThresholds { max_time_increase: 0.25, time_slack_millis: 50, max_memory_increase: 0.25, max_ratio_decrease: 0.001 }
+    }
+}
+
+/// A metric of a benchmark that got worse than its baseline by more than the threshold allows
+#[derive(Debug, Clone, Serialize)]
+pub struct Regression {
+    pub benchmark: String,
+    pub metric: String,
+    pub baseline: f64,
+    pub current: f64,
+}
+
+impl BenchSuite {
+    pub fn load(path: &Path) -> Result<Self, StitchError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| StitchError::io(path, e))?;
+        toml::from_str(&contents).map_err(|e| StitchError::format(path, e))
+    }
+
+    /// The benchmarks whose names contain `filter`, or all of them if it's None
+    #[allow(clippy::unnecessary_map_or)] // `Option::is_none_or` needs Rust 1.82
+    pub fn filtered(&self, filter: Option<&str>) -> Vec<&Benchmark> {
+        self.benchmark.iter().filter(|b| filter.map_or(true, |filter| b.name.contains(filter))).collect()
+    }
+}
+
+impl Benchmark {
+    /// Run the benchmark `runs` times in this process. The peak memory is that of the whole process, so for it to
+    /// mean anything each benchmark should get a process of its own, as with the `bench` binary
+    pub fn run(&self, runs: usize) -> Result<BenchResult, StitchError> {
+        let fmt = InputFormat::from_str(&self.fmt, true)
+            .map_err(|e| StitchError::InvalidInput(format!("benchmark {}: bad fmt {:?}: {e}", self.name, self.fmt)))?;
+        let mut cfg = MultistepCompressionConfig::from_args(std::iter::once("compress").chain(self.args.split_whitespace()))
+            .map_err(|e| StitchError::InvalidInput(format!("benchmark {}: {e}", self.name)))?;
+        cfg.silent = true;
+
+        let mut res = BenchResult { name: self.name.clone(), wall_millis: u64::MAX, peak_rss_kb: None, compression_ratio: 0., num_abstractions: 0, runs };
+        for _ in 0..runs.max(1) {
+            let tstart = Instant::now();
+            let input = fmt.load_programs_and_tasks(&self.file)?;
+            let (_, out) = multistep_compression(&input.train_programs, input.tasks.clone(), input.weights.clone(), input.name_mapping.clone(), input.inventions.clone(), None, None, &cfg)?;
+            res.wall_millis = res.wall_millis.min(tstart.elapsed().as_millis() as u64);
+            res.peak_rss_kb = res.peak_rss_kb.max(peak_rss_kb());
+            res.compression_ratio = out["compression_ratio"].as_f64().unwrap();
+            res.num_abstractions = out["num_abstractions"].as_u64().unwrap() as usize;
+        }
+        Ok(res)
+    }
+}
+
+impl BenchReport {
+    pub fn load(path: &Path) -> Result<Self, StitchError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| StitchError::io(path, e))?;
+        serde_json::from_str(&contents).map_err(|e| StitchError::format(path, e))
+    }
+
+    /// Every metric that regressed past `thresholds` compared to the benchmark of the same name in `baseline`.
+    /// Benchmarks that aren't in the baseline are skipped, as is memory if either side couldn't measure it
+    pub fn regressions(&self, baseline: &BenchReport, thresholds: &BenchThresholds) -> Vec<Regression> {
+        let mut regressions = vec![];
+        for res in &self.benchmarks {
+            let old = match baseline.benchmarks.iter().find(|old| old.name == res.name) {
+                Some(old) => old,
+                None => continue,
+            };
+            let mut check = |metric: &str, baseline: f64, current: f64, regressed: bool| {
+                if regressed {
+                    regressions.push(Regression { benchmark: res.name.clone(), metric: metric.to_string(), baseline, current });
+                }
+            };
+            let (old_millis, new_millis) = (old.wall_millis as f64, res.wall_millis as f64);
+            check("wall_millis", old_millis, new_millis,
+                new_millis > old_millis * (1. + thresholds.max_time_increase) && new_millis > old_millis + thresholds.time_slack_millis as f64);
+            if let (Some(old_kb), Some(new_kb)) = (old.peak_rss_kb, res.peak_rss_kb) {
+                check("peak_rss_kb", old_kb as f64, new_kb as f64, new_kb as f64 > old_kb as f64 * (1. + thresholds.max_memory_increase));
+            }
+            check("compression_ratio", old.compression_ratio, res.compression_ratio,
+                res.compression_ratio < old.compression_ratio * (1. - thresholds.max_ratio_decrease));
+        }
+        regressions
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let memory = self.peak_rss_kb.map_or("?".to_string(), |kb| format!("{:.1}MB", kb as f64 / 1000.));
+        write!(f, "{}: {}ms, {memory} peak, compression ratio {:.2} with {} abstractions", self.name, self.wall_millis, self.compression_ratio, self.num_abstractions)
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let change = (self.current - self.baseline) / self.baseline * 100.;
+        write!(f, "{}: {} went from {} to {} ({change:+.1}%)", self.benchmark, self.metric, self.baseline, self.current)
+    }
+}
@@ -0,0 +1,99 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Time a suite of compression runs over `data/`, recording wall time, peak memory and compression ratio. With
+/// --baseline, exits with status 2 if any of them got worse than the baseline by more than the thresholds allow.
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Bench")]
+pub struct Args {
+    /// .toml file listing the benchmarks to run
+    #[clap(long, parse(from_os_str), default_value = "data/bench.toml")]
+    pub suite: PathBuf,
+
+    /// json file to write the results to, which can be used as a --baseline later
+    #[clap(short, long, parse(from_os_str), default_value = "out/bench.json")]
+    pub out: PathBuf,
+
+    /// results of an earlier run to compare against
+    #[clap(long, parse(from_os_str))]
+    pub baseline: Option<PathBuf>,
+
+    /// only run the benchmarks whose names contain this
+    #[clap(long)]
+    pub filter: Option<String>,
+
+    /// how many times to run each benchmark, keeping the fastest time
+    #[clap(long, default_value = "1")]
+    pub runs: usize,
+
+    #[clap(flatten)]
+    pub thresholds: BenchThresholds,
+
+    /// run just this benchmark and print its result as json. Each benchmark is run this way in a process of its own
+    /// so its peak memory isn't that of an earlier, bigger one
+    #[clap(long, hide = true)]
+    pub child: Option<String>,
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(regressions) if !regressions.is_empty() => {
+            eprintln!("{} {} regression{} against the baseline:", "Error:".red().bold(), regressions.len(), if regressions.len() == 1 { "" } else { "s" });
+            for regression in regressions {
+                eprintln!("  {regression}");
+            }
+            std::process::exit(2);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{} {e}", "Error:".red().bold());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: Args) -> Result<Vec<Regression>, StitchError> {
+    let suite = BenchSuite::load(&args.suite)?;
+
+    if let Some(name) = &args.child {
+        let benchmark = suite.benchmark.iter().find(|b| &b.name == name)
+            .ok_or_else(|| StitchError::InvalidInput(format!("no benchmark named {name}")))?;
+        println!("{}", serde_json::to_string(&benchmark.run(args.runs)?).unwrap());
+        return Ok(vec![])
+    }
+
+    // read before running anything so a bad path doesn't waste a whole run
+    let baseline = args.baseline.as_ref().map(|path| BenchReport::load(path)).transpose()?;
+
+    let mut report = BenchReport::default();
+    for benchmark in suite.filtered(args.filter.as_deref()) {
+        let res = run_child(&args, &benchmark.name)?;
+        println!("{res}");
+        report.benchmarks.push(res);
+    }
+
+    write_output(&args.out, serde_json::to_string_pretty(&report).unwrap())?;
+    println!("Wrote to {:?}", args.out);
+
+    Ok(baseline.map_or(vec![], |baseline| report.regressions(&baseline, &args.thresholds)))
+}
+
+/// run one benchmark in a new process of this binary, see `--child`
+fn run_child(args: &Args, name: &str) -> Result<BenchResult, StitchError> {
+    let exe = std::env::current_exe().map_err(|e| StitchError::InvalidInput(format!("can't find the bench binary: {e}")))?;
+    let output = Command::new(&exe)
+        .arg("--suite").arg(&args.suite)
+        .arg("--runs").arg(args.runs.to_string())
+        .arg("--child").arg(name)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| StitchError::io(&exe, e))?;
+    if !output.status.success() {
+        return Err(StitchError::InvalidInput(format!("benchmark {name} failed ({})", output.status)))
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    serde_json::from_str(stdout.trim()).map_err(|e| StitchError::InvalidInput(format!("benchmark {name} gave a bad result {stdout:?}: {e}")))
+}
@@ -0,0 +1,44 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Compute the call graph between the abstractions in the output json of `compress`
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Callgraph")]
+pub struct Args {
+    /// output json of a `compress` run
+    #[clap(parse(from_os_str), default_value = "out/out.json")]
+    pub file: PathBuf,
+
+    /// Graphviz DOT output file
+    #[clap(long, parse(from_os_str), default_value = "out/callgraph.dot")]
+    pub dot: PathBuf,
+
+    /// JSON adjacency list output file
+    #[clap(long, parse(from_os_str), default_value = "out/callgraph.json")]
+    pub json: PathBuf,
+}
+
+fn write(path: &Path, contents: String) -> Result<(), StitchError> {
+    write_output(path, contents)?;
+    println!("Wrote to {path:?}");
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), StitchError> {
+    let out: Value = serde_json::from_str(&std::fs::read_to_string(&args.file).map_err(|e| StitchError::io(&args.file, e))?)
+        .map_err(|e| StitchError::format(&args.file, e))?;
+    let graph = CallGraph::from_json(&out).map_err(|e| StitchError::format(&args.file, e))?;
+
+    write(&args.dot, graph.to_dot())?;
+    write(&args.json, serde_json::to_string_pretty(&graph.to_json()).unwrap())
+}
@@ -1,7 +1,8 @@
 use stitch_core::*;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use serde::Serialize;
 use std::path::PathBuf;
+use serde_json::Value;
 
 /// Stitch
 #[derive(Parser, Debug, Serialize)]
@@ -25,32 +26,149 @@ pub struct Args {
     #[clap(long)]
     pub save_rewritten: Option<PathBuf>,    
 
+    /// the format to use for --save-rewritten, e.g. 'programs-list' for a JSON array, 'sexp' for one program per line, or
+    /// 'jsonl' to stream them out one JSON value per line (pairs well with --no-json-programs for very large corpora)
+    #[clap(long, arg_enum, default_value = "programs-list")]
+    pub save_rewritten_fmt: InputFormat,
+
+    /// resume a previous run from its output json (or a --checkpoint file), continuing until --iterations
+    /// abstractions have been found in total. The input file is still needed for the tasks and any existing inventions.
+    /// The previous run must not have used --no-json-programs (its output has no programs to resume from), and if it
+    /// used --shuffle, --truncate or --skip-invalid its input can't have tasks or weights, since they'd no longer line
+    /// up with its programs.
+    #[clap(long, parse(from_os_str))]
+    pub resume: Option<PathBuf>,
+
+    /// with --fmt dreamcoder, also write a DreamCoder checkpoint fragment here: the input's DSL with the new
+    /// #(lambda ...) inventions added, and its frontiers with the programs rewritten. Implies --rewritten-dreamcoder
+    #[clap(long, parse(from_os_str))]
+    pub dreamcoder_out: Option<PathBuf>,
+
+    /// write a CSV with one row per iteration (utility, costs, uses, timings, search stats, ...) to this path. Iterations
+    /// are numbered from 0, like in the logs
+    #[clap(long, parse(from_os_str))]
+    pub csv: Option<PathBuf>,
+
     #[clap(flatten)]
     pub multistep: MultistepCompressionConfig,
 
+    #[clap(flatten)]
+    pub tree: TreeConfig,
+
+    #[clap(flatten)]
+    pub log: LogConfig,
+
 }
 
 fn main() {
-    let args = Args::parse();
+    if let Err(e) = parse_args().and_then(run) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
+}
 
-    let input = args.fmt.load_programs_and_tasks(&args.file).unwrap();
+fn parse_args() -> Result<Args, StitchError> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.multistep = args.multistep.resolve(&matches)?;
+    Ok(args)
+}
 
-    let (step_results, json_res) = multistep_compression(&input.train_programs, input.tasks, None, input.name_mapping, None, &args.multistep);
+fn run(mut args: Args) -> Result<(), StitchError> {
+    init_logging(&args.log)?;
 
-    let out_path = &args.out;
-    if let Some(out_path_dir) = out_path.parent() {
-        if !out_path_dir.exists() {
-            std::fs::create_dir_all(out_path_dir).unwrap();
+    if args.dreamcoder_out.is_some() {
+        if !matches!(args.fmt, InputFormat::Dreamcoder) {
+            return Err(StitchError::InvalidInput("--dreamcoder-out needs --fmt dreamcoder".to_string()))
+        }
+        if args.multistep.shuffle || args.multistep.truncate.is_some() || args.multistep.skip_invalid {
+            return Err(StitchError::InvalidInput("--dreamcoder-out can't be used with --shuffle, --truncate or --skip-invalid since the frontiers would no longer line up".to_string()))
         }
+        args.multistep.step.rewritten_dreamcoder = true;
     }
 
-    std::fs::write(out_path, serde_json::to_string_pretty(&json_res).unwrap()).unwrap();
+    let input = args.fmt.load_programs_and_tasks_cfg(&args.file, &args.tree)?;
+
+    let checkpoint: Option<Checkpoint> = args.resume.as_ref().map(|resume| Checkpoint::load(resume)).transpose()?;
+    input.check_records_line_up(&args.multistep, checkpoint.as_ref())?;
+
+    let (step_results, mut json_res) = if let Some(checkpoint) = &checkpoint {
+        multistep_compression_resume(checkpoint, input.tasks.clone(), input.weights.clone(), input.name_mapping.clone(), input.inventions.clone(), None, &args.multistep)?
+    } else {
+        multistep_compression(&input.train_programs, input.tasks.clone(), input.weights.clone(), input.name_mapping.clone(), input.inventions.clone(), None, None, &args.multistep)
+            .map_err(|e| input.locate_error(e))?
+    };
+
+    // programs skipped by --skip-invalid have no rewritten version, so we leave their records out too
+    let skipped: Vec<usize> = json_res["invalid_programs"].as_array().map_or(vec![], |invalid| invalid.iter().filter_map(|p| p["index"].as_u64()).map(|i| i as usize).collect());
+    if let (Some(invalid), Some(lines)) = (json_res["invalid_programs"].as_array_mut(), &input.lines) {
+        for (p, i) in invalid.iter_mut().zip(skipped.iter()) {
+            p["line"] = lines[*i].into();
+        }
+    }
+    let input = if skipped.is_empty() { input } else { input.without_programs(&skipped) };
+
+    // the programs after the last abstraction, or whatever we started from if no new abstractions were found
+    let rewritten: Vec<String> = match (step_results.last(), &checkpoint) {
+        (Some(res), _) => res.rewritten.iter().map(|p| p.to_string()).collect(),
+        (None, Some(checkpoint)) => checkpoint.rewritten.iter().map(|p| p.to_string()).collect(),
+        (None, None) => input.train_programs.clone(),
+    };
+
+    // for formats with per-program records (ids, metadata, ...) we output the records with their programs rewritten
+    if !args.multistep.no_json_programs {
+        if let Some(records) = input.rewritten_records(&rewritten) {
+            json_res["rewritten"] = records.into();
+        }
+    }
+
+    // for tree inputs we also give the library and rewritten programs back as trees
+    if let Some(enc) = &input.tree {
+        for abstraction in json_res["abstractions"].as_array_mut().unwrap() {
+            let body = abstraction["body"].as_str().unwrap().to_string();
+            abstraction["body_tree"] = tree_of_program(&body, enc).map_err(|e| StitchError::parse(None, &body, e))?;
+        }
+        if !args.multistep.no_json_programs {
+            json_res["rewritten_tree"] = rewritten.iter().enumerate()
+                .map(|(i,p)| tree_of_program(p, enc).map_err(|e| StitchError::parse(Some(i), p, e)))
+                .collect::<Result<Vec<Value>,_>>()?.into();
+        }
+    }
+
+    if let Some(dc_out_path) = &args.dreamcoder_out {
+        // every abstraction in the output json, including any resumed ones
+        let inventions: Vec<String> = json_res["abstractions"].as_array().unwrap().iter().map(|abstraction| abstraction["dreamcoder"].as_str().unwrap().to_string()).collect();
+        let dc_json: Value = serde_json::from_str(&std::fs::read_to_string(&args.file).map_err(|e| StitchError::io(&args.file, e))?)
+            .map_err(|e| StitchError::format(&args.file, e))?;
+        let rewritten_dreamcoder: Vec<String> = match (step_results.last(), &checkpoint) {
+            (Some(res), _) => res.rewritten_dreamcoder.clone().unwrap(),
+            (None, Some(checkpoint)) => checkpoint.rewritten_dreamcoder.clone()
+                .ok_or_else(|| StitchError::InvalidInput("checkpoint has no rewritten_dreamcoder programs to resume from".to_string()))?,
+            // nothing was learned so the frontiers stay as they were
+            (None, None) => dc_json["frontiers"].as_array().unwrap().iter()
+                .flat_map(|frontier| frontier["programs"].as_array().unwrap().iter().map(|p| p["program"].as_str().unwrap().to_string()))
+                .collect(),
+        };
+        let dc_checkpoint = dreamcoder_checkpoint(&dc_json, &inventions, &rewritten_dreamcoder).map_err(|e| StitchError::format(&args.file, e))?;
+        write_checkpoint(dc_out_path, &dc_checkpoint)?;
+        if !args.multistep.silent{ println!("Wrote dreamcoder checkpoint to {dc_out_path:?}") };
+    }
+
+    let out_path = &args.out;
+    write_output(out_path, serde_json::to_string_pretty(&json_res).unwrap())?;
     if !args.multistep.silent{ println!("Wrote to {out_path:?}") };
+    if let Some(csv_path) = &args.csv {
+        // iterations are numbered from 0 like in the logs, continuing on from any resumed ones
+        let first_iteration = checkpoint.as_ref().map_or(0, |checkpoint| checkpoint.abstractions.len());
+        write_output(csv_path, csv_of_step_results(&step_results, first_iteration))?;
+        if !args.multistep.silent{ println!("Wrote iteration stats to {csv_path:?}") };
+    }
     if let Some(out_path) = args.save_rewritten {
         if !args.multistep.silent{ println!("Wrote rewritten things to {out_path:?}") };
-        std::fs::write(&out_path, serde_json::to_string_pretty(&step_results.iter().last().unwrap().rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>()).unwrap()).unwrap();
+        args.save_rewritten_fmt.write_programs(&out_path, &rewritten, &input)?;
     }
 
+    Ok(())
 }
 
 
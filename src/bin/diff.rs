@@ -0,0 +1,59 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Compare the output jsons of two `compress` runs: which abstractions were kept (matching up to renaming of
+/// their arguments and of the abstractions they call), added or removed, and how the compression ratio and the
+/// cost of each rewritten program changed.
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Diff")]
+pub struct Args {
+    /// output json of the old run
+    #[clap(parse(from_os_str))]
+    pub old: PathBuf,
+
+    /// output json of the new run
+    #[clap(parse(from_os_str))]
+    pub new: PathBuf,
+
+    /// how many of the programs whose rewritten cost changed the most to show
+    #[clap(long, default_value = "10")]
+    pub top: usize,
+
+    /// also write the full diff to this json file
+    #[clap(long, parse(from_os_str))]
+    pub json: Option<PathBuf>,
+
+    /// the cost function rewritten programs are compared under
+    #[clap(flatten)]
+    pub cost: CostConfig,
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
+}
+
+fn read_json(path: &Path) -> Result<Value, StitchError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| StitchError::io(path, e))?;
+    serde_json::from_str(&contents).map_err(|e| StitchError::format(path, e))
+}
+
+fn run(args: Args) -> Result<(), StitchError> {
+    let old = read_json(&args.old)?;
+    let new = read_json(&args.new)?;
+    let diff = LibraryDiff::new(&old, &new, &args.cost.expr_cost())
+        .map_err(|e| StitchError::InvalidInput(format!("can't diff {} and {}: {e}", args.old.display(), args.new.display())))?;
+
+    print!("{}", diff.summary(args.top));
+
+    if let Some(json_path) = &args.json {
+        std::fs::write(json_path, serde_json::to_string_pretty(&diff).unwrap()).map_err(|e| StitchError::io(json_path, e))?;
+        println!("Wrote to {json_path:?}");
+    }
+    Ok(())
+}
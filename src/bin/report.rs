@@ -0,0 +1,42 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Turn the output json of `compress` into a self-contained Markdown or HTML report
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Report")]
+pub struct Args {
+    /// output json of a `compress` run
+    #[clap(parse(from_os_str), default_value = "out/out.json")]
+    pub file: PathBuf,
+
+    /// report output file
+    #[clap(short, long, parse(from_os_str), default_value = "out/report.html")]
+    pub out: PathBuf,
+
+    /// 'markdown' or 'html'
+    #[clap(long, arg_enum, default_value = "html")]
+    pub fmt: ReportFormat,
+
+    #[clap(flatten)]
+    pub report: ReportConfig,
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), StitchError> {
+    let out: Value = serde_json::from_str(&std::fs::read_to_string(&args.file).map_err(|e| StitchError::io(&args.file, e))?)
+        .map_err(|e| StitchError::format(&args.file, e))?;
+    let report = report(&out, args.fmt, &args.report).map_err(|e| StitchError::format(&args.file, e))?;
+
+    write_output(&args.out, report)?;
+    println!("Wrote report to {:?}", args.out);
+    Ok(())
+}
@@ -11,13 +11,12 @@ cargo run --bin=rewrite --release
 Sample command: cargo run --bin=rewrite --release -- --program-file data/logo/logo_dc.json --inventions-file out/out.json --dc-fmt
 */
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use serde::Serialize;
 use serde_json::de::from_reader;
 use serde_json::json;
-use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use stitch_core::*;
 use serde_json::Value;
 
@@ -49,108 +48,100 @@ pub struct RewriteArgs {
 
     #[clap(flatten)]
     pub cost: MultistepCompressionConfig,
+
+    #[clap(flatten)]
+    pub tree: TreeConfig,
+
+    #[clap(flatten)]
+    pub log: LogConfig,
 }
 
-// Match the relevant input format.
-#[derive(Serialize)]
-pub struct DcProgram {
-    pub program: String,
+fn main() {
+    if let Err(e) = parse_args().and_then(run) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
 }
-#[derive(Serialize)]
-pub struct DcFrontier {
-    pub task: String,
-    pub programs: Vec<DcProgram>,
+
+fn parse_args() -> Result<RewriteArgs, StitchError> {
+    let matches = RewriteArgs::command().get_matches();
+    let mut args = RewriteArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.cost = args.cost.resolve(&matches)?;
+    Ok(args)
 }
 
-fn main() {
-    let args = RewriteArgs::parse();
+/// read a json file, eg the output json of `compress`
+fn read_json(path: &Path) -> Result<Value, StitchError> {
+    from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?).map_err(|e| StitchError::format(path, format!("json parser error: {e}")))
+}
+
+/// the inventions in the "abstractions" field of a `compress` output json, along with their #(lambda ...) forms
+fn load_inventions(path: &Path) -> Result<(Vec<Invention>, Vec<String>), StitchError> {
+    let inventions_data = read_json(path)?;
+    let abstractions = inventions_data["abstractions"].as_array().ok_or_else(|| StitchError::format(path, "no \"abstractions\" field"))?;
+    let mut inventions: Vec<Invention> = vec![];
+    let mut inv_dreamcoder: Vec<String> = vec![];
+    for (i,invention) in abstractions.iter().enumerate() {
+        let field = |key: &str| invention[key].as_str().ok_or_else(|| StitchError::format(path, format!("abstraction {i} has no string \"{key}\" field")));
+        let body = field("body")?;
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(body).map_err(|e| StitchError::parse(None, body, e))?;
+        inventions.push(Invention {
+            body: ExprOwned::new(set, idx),
+            arity: invention["arity"].as_u64().ok_or_else(|| StitchError::format(path, format!("abstraction {i} has no integer \"arity\" field")))? as usize,
+            name: field("name")?.to_string(),
+        });
+        // only needed for dreamcoder output
+        inv_dreamcoder.push(invention["dreamcoder"].as_str().unwrap_or_default().to_string());
+    }
+    Ok((inventions, inv_dreamcoder))
+}
+
+fn run(args: RewriteArgs) -> Result<(), StitchError> {
+    init_logging(&args.log)?;
 
     // Read in the programs and any previous inventions from the DSL.
     let input = args
         .fmt
-        .load_programs_and_tasks(&args.program_file)
-        .unwrap();
+        .load_programs_and_tasks_cfg(&args.program_file, &args.tree)?;
 
     // Read in library to rewrite.
     // This should be in {abstractions: [{name: , body:}]}
-    let inventions_data: Value =
-        from_reader(File::open(&args.inventions_file).expect("file not found"))
-            .expect("json deserializing error");
-    let inventions = inventions_data["abstractions"].as_array().unwrap();
-
-
-    let mut name_mapping = input.name_mapping.clone().unwrap_or_default();
-    name_mapping.extend(inventions.iter().map(|invention| (invention["name"].as_str().unwrap().to_string(),invention["dreamcoder"].as_str().unwrap().to_string())));
-    name_mapping.sort_by_key(|(_, dc_name)| dc_name.len());
-
-    let inventions: Vec<Invention> = inventions
-        .iter()
-        .map(|invention| Invention {
-            body: {
-                let mut set = ExprSet::empty(Order::ChildFirst, false, false);
-                let idx = set.parse_extend(invention["body"].as_str().unwrap()).unwrap();
-                ExprOwned::new(set, idx)
-            },
-            arity: invention["arity"].as_u64().unwrap() as usize,
-            name: invention["name"].as_str().unwrap().parse().unwrap(),
-        })
-        .collect();
-     println!("Number of inventions: {}", inventions.len());
-
-    let mut rewritten_frontiers: HashMap<String, Vec<String>> = HashMap::new();
-
-    
-    let rewritten: Vec<String> = rewrite_with_inventions(&input.train_programs, &inventions[..], &args.cost).0;
-
-    match args.fmt {
-        InputFormat::Dreamcoder => {
+    let (inventions, inv_dreamcoder) = load_inventions(&args.inventions_file)?;
+    println!("Number of inventions: {}", inventions.len());
 
-            // Rewrite back the lambda and optionally rewrite back the DC invention format.
-            for (i, pretty_program) in rewritten.iter().enumerate() {
-                let task_name = input.tasks.clone().map(|tasks| tasks[i].clone()).unwrap_or_else(||i.to_string());
-                let mut pretty_program = pretty_program.to_string();
-                if args.dreamcoder_output {
-                    for (name, dc_translation) in name_mapping.iter().rev() {
-                        pretty_program = replace_prim_with(&pretty_program, name, dc_translation);
-                    }
-                }
-
-                rewritten_frontiers
-                    .entry(task_name)
-                    .or_default()
-                    .push(
-                        pretty_program
-                            .replace("(lam ", "(lambda ")
-                            .clone(),
-                    );
-            }
-            fn rewritten_to_dc_fmt_frontiers(
-                task_name: &str,
-                string_programs: &[String],
-            ) -> DcFrontier {
-                let programs = string_programs
-                    .iter()
-                    .map(|p| DcProgram {
-                        program: p.to_string(),
-                    })
-                    .collect();
-                
-                DcFrontier {
-                    task: task_name.to_string(),
-                    programs,
-                }
-            }
-            let dc_fmt_frontiers: Vec<DcFrontier> = rewritten_frontiers
-                .iter()
-                .map(|(t, ps)| rewritten_to_dc_fmt_frontiers(t, ps))
-                .collect();
+    let rewritten: Vec<String> = rewrite_with_inventions(&input.train_programs, &inventions[..], &args.cost).map_err(|e| input.locate_error(e))?.0;
 
-            let json: Value = json!({ "frontiers": dc_fmt_frontiers });
-            std::fs::write(&args.out, serde_json::to_string_pretty(&json).unwrap()).unwrap();
+    let json: Value = match args.fmt {
+        InputFormat::Dreamcoder => {
+            // keeps the input file as is other than the programs
+            let dc_json: Value = read_json(&args.program_file)?;
+            if inv_dreamcoder.iter().any(|inv| inv.is_empty()) {
+                return Err(StitchError::format(&args.inventions_file, "--fmt dreamcoder needs the \"dreamcoder\" field of every abstraction"))
+            }
+            rewritten_dreamcoder_json(&dc_json, &input, &rewritten, &inventions, &inv_dreamcoder, args.dreamcoder_output)?
         },
         InputFormat::ProgramsList => {
-            let json: Value = json!({ "rewritten": rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>() });
-            std::fs::write(&args.out, serde_json::to_string_pretty(&json).unwrap()).unwrap();
+            json!({ "rewritten": rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>() })
         }
-    }
+        InputFormat::Sexp | InputFormat::Jsonl => {
+            return args.fmt.write_programs(&args.out, &rewritten, &input)
+        }
+        InputFormat::JsonTree => {
+            let enc = input.tree_encoding();
+            let trees: Vec<Value> = rewritten.iter().enumerate()
+                .map(|(i,p)| tree_of_program(p, &enc).map_err(|e| StitchError::parse(Some(i), p, e)))
+                .collect::<Result<_,_>>()?;
+            let library: Vec<Value> = inventions.iter().map(|inv| json!({
+                "name": inv.name,
+                "arity": inv.arity,
+                "body": tree_of_expr(inv.body.immut(), &enc),
+            })).collect();
+            json!({ "rewritten": trees, "library": library })
+        }
+        InputFormat::JsonObjects => {
+            json!({ "rewritten": input.rewritten_records(&rewritten).unwrap() })
+        }
+    };
+    std::fs::write(&args.out, serde_json::to_string_pretty(&json).unwrap()).map_err(|e| StitchError::io(&args.out, e))
 }
@@ -0,0 +1,44 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+/// Serve compression, rewriting, matching and stats over line-delimited JSON-RPC 2.0 on stdin/stdout, keeping the
+/// corpus loaded between requests. See [server.rs] for the methods.
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Serve")]
+pub struct Args {
+    /// corpus to load at startup, otherwise send a "load" request first
+    #[clap(parse(from_os_str))]
+    pub file: Option<PathBuf>,
+
+    /// the format of the input file, see [formats.rs]
+    #[clap(long, arg_enum, default_value = "programs-list")]
+    pub fmt: InputFormat,
+
+    #[clap(flatten)]
+    pub tree: TreeConfig,
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("{} {e}", "Error:".red().bold());
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), StitchError> {
+    let corpus = match &args.file {
+        Some(file) => Some(Corpus::new(args.fmt.load_programs_and_tasks_cfg(file, &args.tree)?)),
+        None => None,
+    };
+    let mut server = Server::new(corpus, args.tree, std::io::stdout());
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| StitchError::InvalidInput(format!("failed to read stdin: {e}")))?;
+        server.handle_line(&line);
+    }
+    // let running requests finish once the client closes stdin
+    server.wait();
+    Ok(())
+}
@@ -2,6 +2,7 @@ use stitch_core::*;
 // extern crate log;
 use clap::Parser;
 use serde::Serialize;
+use serde_json::json;
 use std::path::PathBuf;
 
 
@@ -20,38 +21,43 @@ pub struct Args {
     #[clap(long, arg_enum, default_value = "programs-list")]
     pub fmt: InputFormat,
 
+    /// how many of the most repeated subtrees to show
+    #[clap(long, default_value = "10")]
+    pub top: usize,
+
+    /// print the stats as json instead of text
+    #[clap(long)]
+    pub json: bool,
+
+    #[clap(flatten)]
+    pub cost: CostConfig,
+
+    #[clap(flatten)]
+    pub tree: TreeConfig,
 }
 
-fn get_stats(data: &[i32]) -> (f64, f64) {
-    let mean = data.iter().sum::<i32>() as f64 / data.len() as f64;
-    let variance = data.iter().map(|x|f64::powi(*x as f64 - mean, 2)).sum::<f64>() / data.len() as f64;
-    (mean, f64::sqrt(variance))
+fn exit_with(e: StitchError) -> ! {
+    eprintln!("{} {e}", "Error:".red().bold());
+    std::process::exit(1);
 }
 
 fn main() {
 
     let args = Args::parse();
-    let programs = args.fmt.load_programs_and_tasks(&args.file).unwrap().train_programs;
-
-    let programs: Vec<ExprOwned> = programs.iter().map(|p|{
-        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
-        let idx = set.parse_extend(p).unwrap();
-        ExprOwned::new(set,idx)
-    }).collect();
-
-    let cost_fn = ExprCost::dreamcoder();
-    let length_fn = ExprCost::num_terminals();
-
-    let costs = programs.iter().map(|p| p.cost(&cost_fn)).collect::<Vec<i32>>();
-    let lengths = programs.iter().map(|p| p.cost(&length_fn)).collect::<Vec<i32>>();
-    let depths = programs.iter().map(|p| p.depth() as i32).collect::<Vec<i32>>();
-
-    let (mean_cost, std_cost) = get_stats(&costs);
-    let (mean_length, std_length) = get_stats(&lengths);
-    let (mean_depth, std_depth) = get_stats(&depths);
-
-    println!("Number of programs: {}", programs.len());
-    println!("Costs: mean={mean_cost:.2}, std={std_cost:.2}");
-    println!("Lengths: mean={mean_length:.2}, std={std_length:.2}");
-    println!("Depths: mean={mean_depth:.2}, std={std_depth:.2}");
-}
\ No newline at end of file
+    let input = args.fmt.load_programs_and_tasks_cfg(&args.file, &args.tree).unwrap_or_else(|e| exit_with(e));
+
+    let (programs, mut invalid_programs) = parse_programs(&input.train_programs, input.tasks.as_deref());
+    input.locate(&mut invalid_programs);
+    if !invalid_programs.is_empty() {
+        exit_with(StitchError::InvalidPrograms(invalid_programs))
+    }
+    let programs: Vec<ExprOwned> = programs.into_iter().map(|(_, p)| p).collect();
+
+    let stats = CorpusStats::new(&programs, input.tasks.as_deref(), &args.cost.expr_cost(), args.top);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&json!({"cost_config": args.cost, "stats": stats})).unwrap());
+    } else {
+        print!("{stats}");
+    }
+}
@@ -0,0 +1,76 @@
+use stitch_core::*;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// Check a corpus for the properties that compression options assume: closed terms, beta-normal and eta-long
+/// form, consistent lambda tags, and no primitives named like new abstractions. Exits with status 1 if any program
+/// fails to parse or has a violation.
+#[derive(Parser, Debug, Serialize)]
+#[clap(name = "Validate")]
+pub struct Args {
+    /// file to read the programs from
+    #[clap(parse(from_os_str))]
+    pub file: PathBuf,
+
+    /// the format of the input file, see [formats.rs]
+    #[clap(long, arg_enum, default_value = "programs-list")]
+    pub fmt: InputFormat,
+
+    /// also write the violations to this json file
+    #[clap(long, parse(from_os_str))]
+    pub json: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub validate: ValidateConfig,
+
+    #[clap(flatten)]
+    pub tree: TreeConfig,
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{} {e}", "Error:".red().bold());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// whether the corpus is valid
+fn run(args: Args) -> Result<bool, StitchError> {
+    let input = args.fmt.load_programs_and_tasks_cfg(&args.file, &args.tree)?;
+
+    let (parsed, mut invalid_programs) = parse_programs(&input.train_programs, input.tasks.as_deref());
+    input.locate(&mut invalid_programs);
+    let (indices, programs): (Vec<usize>, Vec<ExprOwned>) = parsed.into_iter().unzip();
+    let tasks: Option<Vec<String>> = input.tasks.as_ref().map(|tasks| indices.iter().map(|i| tasks[*i].clone()).collect());
+    let mut violations = validate(&programs, tasks.as_deref(), &args.validate);
+    // back to indices into the whole corpus, counting the ones that didn't parse
+    for v in violations.iter_mut() {
+        v.index = indices[v.index];
+    }
+
+    for e in invalid_programs.iter() {
+        println!("{} {e}", "[PARSE ERROR]".red().bold());
+    }
+    for v in violations.iter() {
+        println!("{} {v}", "[VIOLATION]".yellow().bold());
+    }
+    println!("{} programs: {} failed to parse, {} have violations", input.train_programs.len(), invalid_programs.len(), violations.len());
+
+    if let Some(json_path) = &args.json {
+        let json = json!({
+            "num_programs": input.train_programs.len(),
+            "invalid_programs": invalid_programs,
+            "violations": violations,
+        });
+        std::fs::write(json_path, serde_json::to_string_pretty(&json).unwrap()).map_err(|e| StitchError::io(json_path, e))?;
+        println!("Wrote to {json_path:?}");
+    }
+
+    Ok(invalid_programs.is_empty() && violations.is_empty())
+}
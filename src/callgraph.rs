@@ -0,0 +1,134 @@
+use lambdas::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// The call graph of a learned library: which abstractions each abstraction's body calls. Abstractions that were
+/// already in the input (`prior_abstractions`, e.g. the inventions of a DreamCoder DSL) are included before the learned ones
+#[derive(Debug, Clone, Serialize)]
+pub struct CallGraph {
+    pub nodes: Vec<CallGraphNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallGraphNode {
+    pub name: String,
+    pub arity: usize,
+    pub body: String,
+    /// number of times it's called in the final rewritten programs
+    pub uses: usize,
+    /// 0 if its body calls no other abstractions, otherwise one more than the deepest abstraction it calls
+    pub depth: usize,
+    /// the abstractions its body calls, with how many times it calls each, in the order they were learned
+    pub calls: Vec<(String, usize)>,
+}
+
+impl CallGraph {
+    /// Build the call graph from the output json of a multistep compression run (as written by `compress`)
+    pub fn from_json(out: &Value) -> Result<Self, String> {
+        let prior: &[Value] = out["prior_abstractions"].as_array().map_or(&[], |prior| prior);
+        let abstractions: Vec<&Value> = prior.iter().chain(out["abstractions"].as_array().ok_or("output json has no \"abstractions\" field")?).collect();
+        let rewritten = out["rewritten"].as_array().ok_or("output json has no \"rewritten\" field, was it run with --no-json-programs?")?;
+        let names: Vec<String> = abstractions.iter().map(|a| a["name"].as_str().map(|s| s.to_string()).ok_or_else(|| format!("abstraction without a name: {a}")))
+            .collect::<Result<_,_>>()?;
+        let idx_of_name: FxHashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (name.as_str(), i)).collect();
+
+        let mut uses = vec![0; names.len()];
+        for p in rewritten.iter() {
+            // programs are strings, or records with a "program" field for some input formats
+            let p = p.as_str().or_else(|| p["program"].as_str()).ok_or_else(|| format!("non-string rewritten program: {p}"))?;
+            for (i, count) in prim_counts(p, &idx_of_name)? {
+                uses[i] += count;
+            }
+        }
+
+        let mut nodes: Vec<CallGraphNode> = vec![];
+        for (i, a) in abstractions.iter().enumerate() {
+            let body = a["body"].as_str().ok_or_else(|| format!("abstraction without a body: {a}"))?.to_string();
+            let mut calls = prim_counts(&body, &idx_of_name)?;
+            calls.sort();
+            // abstractions can only call ones learned before them, so their depths are already known
+            let depth = calls.iter().map(|(j, _)| if *j < i { nodes[*j].depth + 1 } else { 1 }).max().unwrap_or(0);
+            nodes.push(CallGraphNode {
+                name: names[i].clone(),
+                arity: a["arity"].as_u64().unwrap_or_default() as usize,
+                body,
+                uses: uses[i],
+                depth,
+                calls: calls.into_iter().map(|(j, count)| (names[j].clone(), count)).collect(),
+            });
+        }
+        Ok(CallGraph { nodes })
+    }
+
+    /// JSON adjacency list, like {"fn_4": {"arity": 2, "uses": 36, "depth": 1, "calls": {"fn_0": 1}}, ...}
+    pub fn to_json(&self) -> Value {
+        let mut res = serde_json::Map::new();
+        for node in self.nodes.iter() {
+            let calls: serde_json::Map<String, Value> = node.calls.iter().map(|(name, count)| (name.clone(), json!(count))).collect();
+            res.insert(node.name.clone(), json!({
+                "arity": node.arity,
+                "body": node.body,
+                "uses": node.uses,
+                "depth": node.depth,
+                "calls": calls,
+            }));
+        }
+        Value::Object(res)
+    }
+
+    /// Graphviz DOT with an edge from each abstraction to the ones it calls, labelled with how many times it calls them.
+    /// Abstractions at the same depth share a rank.
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"));
+        let mut dot = String::new();
+        writeln!(dot, "digraph library {{").unwrap();
+        writeln!(dot, "    rankdir=BT;").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for node in self.nodes.iter() {
+            let label = format!("{} (arity {})\nuses: {} | depth: {}\n{}", node.name, node.arity, node.uses, node.depth, node.body);
+            writeln!(dot, "    {} [label={}];", quote(&node.name), quote(&label)).unwrap();
+        }
+        for node in self.nodes.iter() {
+            for (callee, count) in node.calls.iter() {
+                writeln!(dot, "    {} -> {} [label=\"{count}\"];", quote(&node.name), quote(callee)).unwrap();
+            }
+        }
+        let max_depth = self.nodes.iter().map(|node| node.depth).max().unwrap_or(0);
+        for depth in 0..=max_depth {
+            let same_rank: Vec<String> = self.nodes.iter().filter(|node| node.depth == depth).map(|node| quote(&node.name)).collect();
+            if !same_rank.is_empty() {
+                writeln!(dot, "    {{ rank=same; {}; }}", same_rank.join("; ")).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// (index, count) for each primitive in `program` that's in `idx_of_name`, sorted by index
+fn prim_counts(program: &str, idx_of_name: &FxHashMap<&str, usize>) -> Result<Vec<(usize, usize)>, String> {
+    let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+    let root = set.parse_extend(program)?;
+    let mut counts: FxHashMap<usize, usize> = FxHashMap::default();
+    let mut stack = vec![root];
+    while let Some(idx) = stack.pop() {
+        match &set[idx] {
+            Node::Prim(p) => {
+                if let Some(i) = idx_of_name.get(p.to_string().as_str()) {
+                    *counts.entry(*i).or_default() += 1;
+                }
+            }
+            Node::App(f, x) => {
+                stack.push(*f);
+                stack.push(*x);
+            }
+            Node::Lam(b, _) => stack.push(*b),
+            Node::Var(_, _) | Node::IVar(_) => {}
+        }
+    }
+    let mut counts: Vec<(usize, usize)> = counts.into_iter().collect();
+    counts.sort();
+    Ok(counts)
+}
@@ -0,0 +1,73 @@
+use lambdas::*;
+use serde_json::Value;
+use serde_json::de::from_reader;
+use std::fs::File;
+use std::path::Path;
+use crate::error::StitchError;
+use crate::util::write_output;
+
+/// The state of a multistep compression run after some number of completed iterations, as recorded
+/// in its output json. This is everything needed to pick the run back up with `multistep_compression_resume`.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub original: Vec<String>, // the corpus before any abstractions were learned
+    pub rewritten: Vec<ExprOwned>, // the corpus rewritten under all the abstractions learned so far
+    pub rewritten_dreamcoder: Option<Vec<String>>,
+    pub abstractions: Vec<Value>, // the json of each abstraction learned so far, in order
+    /// whether the run that wrote this shuffled, truncated or dropped invalid programs from its input, in which case
+    /// `original` no longer lines up with the input's tasks and weights
+    pub reordered: bool,
+}
+
+impl Checkpoint {
+    /// load a checkpoint from the output json of a previous (possibly interrupted) run
+    pub fn load(path: &Path) -> Result<Self, StitchError> {
+        let json: Value = from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?)
+            .map_err(|e| StitchError::format(path, format!("json parser error in checkpoint: {e}")))?;
+        Self::from_json(&json).map_err(|e| StitchError::format(path, e))
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        // programs are usually strings, but `compress` writes records like {"program": ..., "id": ...} for some input formats
+        let strings = |key: &str| -> Result<Vec<String>, String> {
+            json[key].as_array().ok_or_else(|| format!("checkpoint is missing the `{key}` field"))?
+                .iter().map(|p| p.as_str().or_else(|| p["program"].as_str()).map(|s| s.to_string()).ok_or_else(|| format!("non-string program in `{key}`: {p}")))
+                .collect()
+        };
+        if json["args"]["no_json_programs"].as_bool() == Some(true) || (json["original"].is_null() && json["abstractions"].is_array()) {
+            return Err("checkpoint was written with --no-json-programs so it has no programs to resume from".to_string());
+        }
+        let original = strings("original")?;
+        let rewritten: Vec<ExprOwned> = strings("rewritten")?.iter().map(|p| {
+            let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+            let idx = set.parse_extend(p)?;
+            Ok(ExprOwned::new(set,idx))
+        }).collect::<Result<_,String>>()?;
+        if original.len() != rewritten.len() {
+            return Err(format!("checkpoint has {} original programs but {} rewritten ones", original.len(), rewritten.len()));
+        }
+        let rewritten_dreamcoder = if json["rewritten_dreamcoder"].is_null() { None } else { Some(strings("rewritten_dreamcoder")?) };
+        let abstractions = json["abstractions"].as_array().ok_or("checkpoint is missing the `abstractions` field")?.clone();
+        for abstraction in abstractions.iter() {
+            if !abstraction["name"].is_string() || !abstraction["dreamcoder"].is_string() {
+                return Err(format!("checkpoint abstraction is missing its `name` or `dreamcoder` field: {abstraction}"));
+            }
+        }
+        let args = &json["args"];
+        let reordered = args["shuffle"].as_bool() == Some(true) || !args["truncate"].is_null() || args["skip_invalid"].as_bool() == Some(true);
+        Ok(Checkpoint { original, rewritten, rewritten_dreamcoder, abstractions, reordered })
+    }
+
+    /// [fn_i, #dreamcoder invention] pairs for the abstractions learned so far, in the order they were learned
+    pub fn name_mapping(&self) -> Vec<(String, String)> {
+        self.abstractions.iter().map(|abstraction|
+            (abstraction["name"].as_str().unwrap().to_string(), abstraction["dreamcoder"].as_str().unwrap().to_string())
+        ).collect()
+    }
+}
+
+/// write an output json to `path`, creating any missing parent directories. This is what gets written
+/// after each iteration when `--checkpoint` is set, and it's also a valid checkpoint to resume from.
+pub fn write_checkpoint(path: &Path, json: &Value) -> Result<(), StitchError> {
+    write_output(path, serde_json::to_string_pretty(json).unwrap())
+}
@@ -8,17 +8,22 @@ use std::hash::Hash;
 use itertools::Itertools;
 use serde_json::json;
 use clap::{Parser};
-use serde::Serialize;
+use serde::{Serialize, Deserialize, Deserializer, de::Error as _};
 use std::thread;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use parking_lot::Mutex;
 use std::ops::DerefMut;
 use std::collections::BinaryHeap;
+use std::path::PathBuf;
+use std::borrow::Cow;
 use rand::Rng;
+use log::{debug, info, warn};
 
 /// Multistep Compression
-#[derive(Parser, Debug, Serialize, Clone)]
+#[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[clap(name = "Multistep Compression")]
+#[serde(default, deny_unknown_fields)]
 pub struct MultistepCompressionConfig {
 
     /// Maximum number of iterations to run compression for (number of inventions to find, though
@@ -59,13 +64,61 @@ pub struct MultistepCompressionConfig {
     #[clap(long)]
     pub verbose_rewrite: bool,
 
+    /// Write the output json to this path after every completed iteration, so that a run that gets killed
+    /// loses at most one step. The file can be resumed from with `--resume` in `compress`
+    #[clap(long, parse(from_os_str))]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Leave the `original`, `rewritten` and `rewritten_dreamcoder` program lists out of the output json, keeping just the
+    /// library and summary statistics. For very large corpora, combine with `--save-rewritten-fmt jsonl` in `compress` to
+    /// stream the rewritten programs to a separate file instead. Checkpoints still include the programs so they can be resumed.
+    #[clap(long)]
+    pub no_json_programs: bool,
+
+    /// Leave out programs that fail to parse and compress the rest, instead of stopping with a list of every failure.
+    /// The skipped programs are listed under `invalid_programs` in the output json
+    #[clap(long)]
+    pub skip_invalid: bool,
+
+    /// Start from a named bundle of options instead of the defaults (see `Preset`): dreamcoder, neurosymbolic or fast.
+    /// Other options given on the command line or in `--config` take precedence
+    #[clap(long, arg_enum)]
+    pub preset: Option<Preset>,
+
+    /// Read options from a .toml or .json file laid out like the `args` of an output json, so the args of a previous run
+    /// can be reused. Options given on the command line take precedence over the file. Like `preset`, only applied by
+    /// `from_args` and `resolve`, which the binaries use to parse their flags
+    #[clap(long, parse(from_os_str))]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Set from another thread to stop compression early with `StitchError::Cancelled`
+    #[clap(skip)]
+    #[serde(skip)]
+    pub cancel: Cancellation,
+
     #[clap(flatten)]
     pub step: CompressionStepConfig,
 }
 
+/// A flag for stopping a compression that's running on another thread, e.g. by the `cancel` method of `serve`.
+/// Clones share the flag, so cancelling a config cancels every compression running with a copy of it.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 /// Args for compression step
-#[derive(Parser, Debug, Serialize, Clone)]
+#[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[clap(name = "Stitch")]
+#[serde(default, deny_unknown_fields)]
 pub struct CompressionStepConfig {
     /// Max arity of abstractions to find (will find arities from 0 to this number inclusive).
     /// Note that scaling with arity can be very expensive
@@ -229,11 +282,31 @@ pub struct CompressionStepConfig {
     pub fused_lambda_tags: FusedLambdaTags,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Serialized as a sorted list of tags, or null if there are none
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Option<Vec<Tag>>", into = "Option<Vec<Tag>>")]
 pub struct FusedLambdaTags {
     tags: Option<FxHashSet<Tag>>,
 }
 
+impl From<Option<Vec<Tag>>> for FusedLambdaTags {
+    fn from(tags: Option<Vec<Tag>>) -> Self {
+        FusedLambdaTags { tags: tags.map(|tags| tags.into_iter().collect()) }
+    }
+}
+
+impl From<FusedLambdaTags> for Option<Vec<Tag>> {
+    fn from(fused: FusedLambdaTags) -> Self {
+        fused.tags.map(|tags| tags.into_iter().sorted().collect())
+    }
+}
+
+impl FusedLambdaTags {
+    pub fn tags(&self) -> Option<&FxHashSet<Tag>> {
+        self.tags.as_ref()
+    }
+}
+
 // parse from a string like "1,2,3"
 impl std::str::FromStr for FusedLambdaTags {
     type Err = String;
@@ -241,7 +314,7 @@ impl std::str::FromStr for FusedLambdaTags {
         if s.is_empty() {
             return Ok(FusedLambdaTags { tags: None })
         }
-        let tags = s.split(',').map(|s| s.parse::<Tag>().unwrap()).collect();
+        let tags = s.split(',').map(|s| s.parse::<Tag>().map_err(|e| format!("invalid tag {s:?}: {e}"))).collect::<Result<_,_>>()?;
         Ok(FusedLambdaTags { tags: Some(tags) })
     }
 }
@@ -275,6 +348,11 @@ impl Default for CompressionStepConfig {
         Self::new()
     }
 }
+impl Default for CostConfig {
+    fn default() -> Self {
+        Self::parse_from("cost".split_whitespace())
+    }
+}
 
 /// A Pattern is a partial abstraction which may have holes
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -337,8 +415,9 @@ fn zids_of_ivar_of_expr(expr: &ExprOwned, zid_of_zip: &FxHashMap<Vec<ZNode>,ZId>
 
 
 /// Args for cost function used
-#[derive(Parser, Debug, Serialize, Clone)]
+#[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[clap(name = "Cost Config")]
+#[serde(default, deny_unknown_fields)]
 pub struct CostConfig {
     /// Sets cost for lambdas
     #[clap(long, default_value = "1")]
@@ -378,7 +457,7 @@ impl CostConfig {
 impl Pattern {
     /// create a single hole pattern `??`
     //#[inline(never)]
-    fn single_hole(corpus_span: &Span, cost_of_node_all: &[i32], num_paths_to_node: &[i32], set: &ExprSet, cost_fn: &ExprCost, cfg: &CompressionStepConfig) -> Self {
+    fn single_hole(corpus_span: &Span, cost_of_node_all: &[i32], num_paths_to_node: &[i32], set: &ExprSet, cost_fn: &ExprCost, cfg: &CompressionStepConfig) -> Result<Self, StitchError> {
         let body_utility = 0;
         let mut match_locations: Vec<Idx> = corpus_span.clone().collect();
         match_locations.sort(); // we assume match_locations is always sorted
@@ -402,7 +481,9 @@ impl Pattern {
         
         if cfg.eta_long {
 
-            assert!(cfg.utility_by_rewrite || cfg.no_mismatch_check, "eta long form requires utility_by_rewrite or no_mismatch_check");
+            if !cfg.utility_by_rewrite && !cfg.no_mismatch_check {
+                return Err(StitchError::InvalidInput("eta long form requires utility_by_rewrite or no_mismatch_check".to_string()))
+            }
 
             for node in corpus_span.clone() {
                 if let Node::App(f,_) = &set[node] {
@@ -412,7 +493,7 @@ impl Pattern {
                     match_locations.retain(|node| node != f);
 
                     if let Node::Lam(_, _) = &set[*f] {
-                        panic!("corpus was not in beta-normal form")
+                        return Err(StitchError::InvalidInput(format!("corpus was not in beta-normal form: {}", set.get(node))))
                     }
                 }
             }
@@ -425,13 +506,17 @@ impl Pattern {
                             // continue if there are free vars in the expression - eg $0 can validly appear to either the left or right of an app
                             continue 
                         }
-                        assert!(match_locations.contains(x), "corpus was not in eta long form (?). This appeared both to the left and right of an app: {}; for example it is to the right in: {}", set.get(*x), set.get(node));
+                        if !match_locations.contains(x) {
+                            return Err(StitchError::InvalidInput(format!("corpus was not in eta long form (?). This appeared both to the left and right of an app: {}; for example it is to the right in: {}", set.get(*x), set.get(node))))
+                        }
                     },
                     Node::Lam(b, _) => {
                         if !AnalyzedExpr::new(FreeVarAnalysis).analyze_get(set.get(*b)).is_empty() {
                             continue
                         }
-                        assert!(match_locations.contains(b), "corpus was not in eta long form (?)");
+                        if !match_locations.contains(b) {
+                            return Err(StitchError::InvalidInput(format!("corpus was not in eta long form (?). This appeared both to the left of an app and as a lambda body: {}", set.get(*b))))
+                        }
                     },
                     _ => {}
                 }
@@ -444,7 +529,7 @@ impl Pattern {
         }
 
         let utility_upper_bound = utility_upper_bound(&match_locations, body_utility, cost_of_node_all, num_paths_to_node, cost_fn, cfg);
-        Pattern {
+        Ok(Pattern {
             holes: vec![EMPTY_ZID], // (zid 0 is the empty zipper)
             arg_choices: vec![],
             first_zid_of_ivar: vec![],
@@ -452,7 +537,7 @@ impl Pattern {
             utility_upper_bound,
             body_utility, // 0 body utility
             tracked: cfg.follow.is_some(),
-        }
+        })
     }
     /// convert pattern to an Expr
     fn to_expr(&self, shared: &SharedData) -> ExprOwned {
@@ -648,6 +733,7 @@ pub struct CriticalMultithreadData {
     worklist: BinaryHeap<HeapItem>,
     utility_pruning_cutoff: i32,
     active_threads: FxHashSet<std::thread::ThreadId>, // list of threads currently holding worklist items
+    worklist_high_water: usize, // the longest the worklist has been
 }
 
 /// All the data shared among threads, mostly read-only
@@ -686,6 +772,9 @@ pub struct SharedData {
     pub multistep_cfg: MultistepCompressionConfig,
     pub tracking: Option<Tracking>,
     pub fused_lambda_tags: Option<FxHashSet<Tag>>,
+    pub rewrite_check_error: Mutex<Option<StitchError>>, // first failure seen by a `rewrite_check`, returned once the search is over
+    pub iteration: usize, // which iteration of multistep compression this is, for the observer
+    pub observer: Option<Arc<dyn Observer>>,
 }
 
 fn invalid_metavar_location(shared : &SharedData, node: Idx) -> bool {
@@ -722,9 +811,11 @@ impl CriticalMultithreadData {
             donelist,
             worklist,
             // we allow negative utilities in follow_prune case
-            utility_pruning_cutoff: if !cfg.follow_prune { 0 } else { std::i32::MIN },
+            utility_pruning_cutoff: if !cfg.follow_prune { 0 } else { i32::MIN },
             active_threads: FxHashSet::default(),
+            worklist_high_water: 0,
         };
+        res.worklist_high_water = res.worklist.len();
         res.update(cfg);
         res
     }
@@ -738,7 +829,7 @@ impl CriticalMultithreadData {
         self.donelist.truncate(cfg.inv_candidates);
         // the cutoff is the lowest utility
         // we allow negative utilities in follow_prune case
-        let default_bound = if !cfg.follow_prune { 0 } else { std::i32::MIN };
+        let default_bound = if !cfg.follow_prune { 0 } else { i32::MIN };
         self.utility_pruning_cutoff = if cfg.no_opt_upper_bound { default_bound } else { std::cmp::max(0,self.donelist.last().map(|x|x.utility).unwrap_or(0)) };
     }
 }
@@ -767,23 +858,67 @@ impl Display for Invention {
     }
 }
 
-/// Various tracking stats
-#[derive(Clone,Default, Debug)]
+/// Various tracking stats, counting how often each part of the search ran and each kind of pruning fired.
+/// All zero with `--no-stats`
+#[derive(Clone,Default, Debug, Serialize)]
 pub struct Stats {
-    worklist_steps: usize,
-    finished: usize,
-    calc_final_utility: usize,
-    calc_unargcap: usize,
-    donelist_push: usize,
-    azero_calc_util: usize,
-    azero_calc_unargcap: usize,
-    upper_bound_fired: usize,
+    pub worklist_steps: usize,
+    pub finished: usize,
+    pub calc_final_utility: usize,
+    pub calc_unargcap: usize,
+    pub donelist_push: usize,
+    pub azero_calc_util: usize,
+    pub azero_calc_unargcap: usize,
+    pub upper_bound_fired: usize,
     // conflict_upper_bound_fired: usize,
-    free_vars_fired: usize,
-    single_use_fired: usize,
-    single_task_fired: usize,
-    useless_abstract_fired: usize,
-    force_multiuse_fired: usize,
+    pub free_vars_fired: usize,
+    pub single_use_fired: usize,
+    pub single_task_fired: usize,
+    pub useless_abstract_fired: usize,
+    pub force_multiuse_fired: usize,
+}
+
+impl Stats {
+    /// every counter other than `worklist_steps`, as (name, count) pairs
+    pub fn counters(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("finished", self.finished),
+            ("calc_final_utility", self.calc_final_utility),
+            ("calc_unargcap", self.calc_unargcap),
+            ("donelist_push", self.donelist_push),
+            ("azero_calc_util", self.azero_calc_util),
+            ("azero_calc_unargcap", self.azero_calc_unargcap),
+            ("upper_bound_fired", self.upper_bound_fired),
+            ("free_vars_fired", self.free_vars_fired),
+            ("single_use_fired", self.single_use_fired),
+            ("single_task_fired", self.single_task_fired),
+            ("useless_abstract_fired", self.useless_abstract_fired),
+            ("force_multiuse_fired", self.force_multiuse_fired),
+        ]
+    }
+}
+
+/// Sizes of the biggest data structures of a compression step, and the peak memory use of the whole process up to the
+/// end of the step
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct StepMemory {
+    pub peak_rss_kb: Option<usize>, // see `peak_rss_kb()`, only available on Linux
+    pub expr_set: usize, // nodes in the structurally hashed corpus
+    pub zip_of_zid: usize, // number of zippers
+    pub arg_of_zid_node: usize, // (zipper, node) pairs with an argument, across every zipper
+    pub worklist_high_water: usize, // most partial patterns on the worklist at once
+}
+
+/// How long the parts of a compression step took. `zippers_millis`, `analyses_millis` and `arity_zero_millis` are parts
+/// of `prep_millis`, and only the prep timings are filled in when they're passed to `Observer::prep_finished`
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct StepTimings {
+    pub prep_millis: usize,
+    pub zippers_millis: usize, // get_zippers()
+    pub analyses_millis: usize, // free variable, cost and ivar analyses of the corpus
+    pub arity_zero_millis: usize, // finding the arity zero abstractions the search starts from
+    pub search_millis: usize,
+    pub rewrite_millis: usize, // rewriting the corpus with the chosen abstraction
 }
 
 
@@ -804,6 +939,18 @@ pub enum HoleChoice {
     FewApps,
 }
 
+// accepts both the command line spelling (depth-first) and the serialized one (DepthFirst)
+impl<'de> Deserialize<'de> for HoleChoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let normalize = |s: &str| s.replace('-', "").to_lowercase();
+        <HoleChoice as clap::ArgEnum>::value_variants().iter()
+            .find(|choice| normalize(clap::ArgEnum::to_possible_value(*choice).unwrap().get_name()) == normalize(&s))
+            .cloned()
+            .ok_or_else(|| D::Error::custom(format!("unknown hole choice {s:?}")))
+    }
+}
+
 impl HoleChoice {
     //#[inline(never)]
     fn choose_hole(&self, pattern: &Pattern, shared: &SharedData) -> usize {
@@ -875,11 +1022,17 @@ fn get_worklist_item(
     // sort + truncate + update utility_pruning_cutoff
     crit.update(&shared.cfg); // this also updates utility_pruning_cutoff
 
+    if let Some(observer) = &shared.observer {
+        if let Some(best) = crit.donelist.first().filter(|best| best.utility > old_best_utility) {
+            observer.new_best(shared.iteration, best.utility, &best.to_expr(shared).to_string());
+        }
+    }
+
     if shared.cfg.verbose_best && crit.donelist.first().map(|x|x.utility).unwrap_or(0) > old_best_utility {
 
         let new_expected_cost = shared.first_train_cost - crit.donelist.first().unwrap().compressive_utility + crit.donelist.first().unwrap().to_expr(shared).cost(&shared.cost_fn);
         let trainratio = shared.first_train_cost as f64 / new_expected_cost as f64;
-        if !shared.cfg.quiet { println!("{} @ step={} util={} trainratio={:.2} for {}", "[new best utility]".blue(), shared.stats.lock().deref_mut().worklist_steps, crit.donelist.first().unwrap().utility, trainratio, crit.donelist.first().unwrap().info(shared)) }
+        if !shared.cfg.quiet { info!(target: "stitch::search", "{} @ step={} util={} trainratio={:.2} for {}", "[new best utility]".blue(), shared.stats.lock().deref_mut().worklist_steps, crit.donelist.first().unwrap().utility, trainratio, crit.donelist.first().unwrap().info(shared)) }
     }
 
     // pull out the newer version of this now that its been updated, since we're returning it at the end
@@ -889,6 +1042,7 @@ fn get_worklist_item(
     let worklist_buf_len = worklist_buf.len();
     // drain from worklist_buf into the actual worklist
     crit.worklist.extend(worklist_buf.drain(..).filter(|heap_item| heap_item.pattern.utility_upper_bound > utility_pruning_cutoff));
+    crit.worklist_high_water = crit.worklist_high_water.max(crit.worklist.len());
     // num pruned by upper bound = num we were gonna add minus change in worklist length
     if !shared.cfg.no_stats { shared.stats.lock().deref_mut().upper_bound_fired += worklist_buf_len - (crit.worklist.len() - old_worklist_len); };
 
@@ -896,6 +1050,9 @@ fn get_worklist_item(
 
     // try to get a new worklist item
     crit.active_threads.remove(&thread::current().id()); // remove ourself from the active threads
+    if shared.multistep_cfg.cancel.is_cancelled() {
+        return None // every thread gives up the next time it comes back for work
+    }
     // if !shared.cfg.quiet { println!("worklist len: {}", crit.worklist.len()) }
 
     loop {
@@ -934,6 +1091,7 @@ fn get_worklist_item(
 }
 
 /// The core top down branch and bound search
+#[allow(clippy::manual_is_multiple_of)] // `is_multiple_of` needs Rust 1.87
 fn stitch_search(
     shared: Arc<SharedData>,
 ) {
@@ -958,9 +1116,24 @@ fn stitch_search(
         for original_pattern in patterns {
 
             if !shared.cfg.no_stats { shared.stats.lock().deref_mut().worklist_steps += 1; };
-            if !shared.cfg.no_stats && shared.cfg.print_stats > 0 && shared.stats.lock().deref_mut().worklist_steps % shared.cfg.print_stats == 0 && !shared.cfg.quiet { println!("{:?} \n\t@ [bound={}; uses={}] chose: {}",shared.stats.lock().deref_mut(),   original_pattern.utility_upper_bound, original_pattern.match_locations.iter().map(|loc| shared.num_paths_to_node[*loc]).sum::<i32>(), original_pattern.to_expr(&shared)) };
+            if !shared.cfg.no_stats && shared.cfg.print_stats > 0 && shared.stats.lock().deref_mut().worklist_steps % shared.cfg.print_stats == 0 && !shared.cfg.quiet { debug!(target: "stitch::search", "{:?} \n\t@ [bound={}; uses={}] chose: {}",shared.stats.lock().deref_mut(),   original_pattern.utility_upper_bound, original_pattern.match_locations.iter().map(|loc| shared.num_paths_to_node[*loc]).sum::<i32>(), original_pattern.to_expr(&shared)) };
+
+            if let Some(observer) = &shared.observer {
+                let worklist_steps = shared.stats.lock().worklist_steps;
+                // interval 0 means never
+                if !shared.cfg.no_stats && worklist_steps.checked_rem(observer.worklist_stats_interval()) == Some(0) {
+                    let crit = shared.crit.lock();
+                    observer.worklist_stats(shared.iteration, &WorklistStats {
+                        worklist_steps,
+                        worklist_len: crit.worklist.len(),
+                        best_utility: crit.donelist.first().map(|best| best.utility),
+                        utility_pruning_cutoff: crit.utility_pruning_cutoff,
+                        counters: shared.stats.lock().counters(),
+                    });
+                }
+            }
 
-            if shared.cfg.verbose_worklist && !shared.cfg.quiet { println!("[bound={}; uses={}] chose: {}", original_pattern.utility_upper_bound, original_pattern.match_locations.iter().map(|loc| shared.num_paths_to_node[*loc]).sum::<i32>(), original_pattern.to_expr(&shared)) }
+            if shared.cfg.verbose_worklist && !shared.cfg.quiet { debug!(target: "stitch::search", "[bound={}; uses={}] chose: {}", original_pattern.utility_upper_bound, original_pattern.match_locations.iter().map(|loc| shared.num_paths_to_node[*loc]).sum::<i32>(), original_pattern.to_expr(&shared)) }
 
             // choose which hole we're going to expand
             let hole_idx: usize = shared.cfg.hole_choice.choose_hole(&original_pattern, &shared);
@@ -988,7 +1161,7 @@ fn stitch_search(
                 for (expands_to, locs) in match_locations.into_iter()
                 .group_by(|loc| &arg_of_loc[loc].expands_to).into_iter()
                 .map(|(expands_to, locs)| (expands_to.clone(), locs.collect::<Vec<Idx>>()))
-                .chain(ivars_expansions.into_iter())
+                .chain(ivars_expansions)
             {
                 // for debugging
                 let tracked = original_pattern.tracked && expands_to == tracked_expands_to(&original_pattern, hole_zid, &shared);
@@ -1012,7 +1185,7 @@ fn stitch_search(
                         && locs.iter().all(|node| shared.tasks_of_node[*node].len() == 1)
                         && locs.iter().all(|node| shared.tasks_of_node[locs[0]].iter().next() == shared.tasks_of_node[*node].iter().next()) {
                     if !shared.cfg.no_stats { shared.stats.lock().deref_mut().single_task_fired += 1; }
-                    if tracked && !shared.cfg.quiet { println!("{} single task pruned when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), zipper_replace(original_pattern.to_expr(&shared), &shared.zip_of_zid[hole_zid], Node::Prim(format!("<{expands_to}>").into()))) }
+                    if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} single task pruned when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), zipper_replace(original_pattern.to_expr(&shared), &shared.zip_of_zid[hole_zid], Node::Prim(format!("<{expands_to}>").into()))) }
                     continue 'expansion;
                 }
 
@@ -1021,7 +1194,7 @@ fn stitch_search(
                 if let ExpandsTo::Var(i, _) = expands_to {
                     if i >= shared.zip_of_zid[hole_zid].iter().filter(|znode|**znode == ZNode::Body).count() as i32 {
                         if !shared.cfg.no_stats { shared.stats.lock().deref_mut().free_vars_fired += 1; };
-                        if tracked && !shared.cfg.quiet { println!("{} pruned by free var in body when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
+                        if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} pruned by free var in body when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
                         continue 'expansion; // free var
                     }
                 }
@@ -1042,7 +1215,7 @@ fn stitch_search(
                 // Pruning (UPPER BOUND): if the upper bound is less than the best invention we've found so far (our cutoff), we can discard this pattern
                 if !shared.cfg.no_opt_upper_bound && util_upper_bound <= weak_utility_pruning_cutoff {
                     if !shared.cfg.no_stats { shared.stats.lock().deref_mut().upper_bound_fired += 1; };
-                    if tracked && !shared.cfg.quiet { println!("{} upper bound ({} < {}) pruned when expanding {} to {}", "[TRACK]".red().bold(), util_upper_bound, weak_utility_pruning_cutoff, original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
+                    if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} upper bound ({} < {}) pruned when expanding {} to {}", "[TRACK]".red().bold(), util_upper_bound, weak_utility_pruning_cutoff, original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
                     continue 'expansion; // too low utility
                 }
 
@@ -1105,7 +1278,7 @@ fn stitch_search(
                                 arg_of_loc_1[loc].shifted_id == arg_of_loc_2[loc].shifted_id)
                             {
                                 if !shared.cfg.no_stats { shared.stats.lock().deref_mut().force_multiuse_fired += 1; };
-                                if tracked && !shared.cfg.quiet { println!("{} force multiuse pruned when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
+                                if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} force multiuse pruned when expanding {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
                                 continue 'expansion;
                             }
                         }
@@ -1157,15 +1330,30 @@ fn stitch_search(
                     if !shared.cfg.no_stats { shared.stats.lock().donelist_push += 1; };
 
                     if shared.cfg.rewrite_check {
-                        // run rewriting just to make sure the assert in it passes
-                        let rw_fast = rewrite_fast(&finished_pattern, &shared, &Node::Prim("fake_inv".into()), &shared.cost_fn);
-                        let (rw_slow, _, _) = rewrite_with_inventions(&shared.programs.iter().map(|p|p.to_string()).collect::<Vec<_>>(), &[finished_pattern.clone().to_invention("fake_inv", &shared)], &shared.multistep_cfg);
-                        for (fast,slow) in rw_fast.iter().zip(rw_slow.iter()) {
-                            assert_eq!(fast.to_string(), slow.to_string());
+                        // run rewriting just to make sure it doesn't hit a cost mismatch and agrees with the slow rewrite
+                        let check = rewrite_fast(&finished_pattern, &shared, &Node::Prim("fake_inv".into()), &shared.cost_fn).and_then(|rw_fast| {
+                            let (rw_slow, _, _) = rewrite_with_inventions_parsed(&shared.programs, &[finished_pattern.clone().to_invention("fake_inv", &shared)], &shared.multistep_cfg)?;
+                            for (fast,slow) in rw_fast.iter().zip(rw_slow.iter()) {
+                                if fast.to_string() != *slow {
+                                    let abstraction = finished_pattern.to_expr(&shared).to_string();
+                                    let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+                                    let idx = set.parse_extend(slow).map_err(|e| StitchError::parse(None, slow, e))?;
+                                    let (expected, actual) = (fast.cost(&shared.cost_fn), ExprOwned::new(set, idx).cost(&shared.cost_fn));
+                                    if expected != actual {
+                                        return Err(StitchError::CostMismatch { abstraction, expected, actual })
+                                    }
+                                    return Err(StitchError::InvalidInput(format!("rewriting with {abstraction} gave {fast} but the slow rewrite used by --rewrite-check gave {slow}")))
+                                }
+                            }
+                            Ok(())
+                        });
+                        if let Err(e) = check {
+                            shared.rewrite_check_error.lock().get_or_insert(e);
+                            continue 'expansion
                         }
                     }
 
-                    if tracked && !shared.cfg.quiet { println!("{} pushed {} to donelist (util: {})", "[TRACK:DONE]".green().bold(), finished_pattern.to_expr(&shared), finished_pattern.utility) }
+                    if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} pushed {} to donelist (util: {})", "[TRACK:DONE]".green().bold(), finished_pattern.to_expr(&shared), finished_pattern.utility) }
 
                     if shared.cfg.inv_candidates == 1 && finished_pattern.utility > weak_utility_pruning_cutoff {
                         // if we're only looking for one invention, we can directly update our cutoff here
@@ -1176,7 +1364,7 @@ fn stitch_search(
 
                 } else {
                     // it's a partial pattern so just add it to the worklist
-                    if tracked && !shared.cfg.quiet { println!("{} pushed {} to work list (bound: {})", "[TRACK]".green().bold(), original_pattern.show_track_expansion(hole_zid, &shared), new_pattern.utility_upper_bound) }
+                    if tracked && !shared.cfg.quiet { info!(target: "stitch::track", "{} pushed {} to work list (bound: {})", "[TRACK]".green().bold(), original_pattern.show_track_expansion(hole_zid, &shared), new_pattern.utility_upper_bound) }
                     worklist_buf.push(HeapItem::new(new_pattern))
                 }
             }
@@ -1185,7 +1373,7 @@ fn stitch_search(
                 // let new = format!("<{}>",tracked_expands_to(&original_pattern, hole_zid, &shared));
                 // let mut s = original_pattern.to_expr(&shared).zipper_replace(&shared.zip_of_zid[hole_zid], &new ).to_string();
                 // s = s.replace(&new, &new.clone().magenta().bold().to_string());
-            if !shared.cfg.quiet { println!("{} pruned when expanding because there were no match locations for the target expansion of {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
+            if !shared.cfg.quiet { info!(target: "stitch::track", "{} pruned when expanding because there were no match locations for the target expansion of {} to {}", "[TRACK]".red().bold(), original_pattern.to_expr(&shared), original_pattern.show_track_expansion(hole_zid, &shared)) }
             }
         
         }
@@ -1260,7 +1448,8 @@ impl FinishedPattern {
             usages,
         };
         if shared.cfg.utility_by_rewrite {
-            let rewritten: Vec<ExprOwned> = rewrite_fast(&res, shared, &Node::Prim("fake_inv".into()), &shared.cost_fn);
+            let rewritten: Vec<ExprOwned> = rewrite_fast(&res, shared, &Node::Prim("fake_inv".into()), &shared.cost_fn)
+                .expect("rewrite_fast skips the cost check when computing utility by rewriting");
             res.compressive_utility = shared.init_cost - shared.root_idxs_of_task.iter().map(|root_idxs|
                 root_idxs.iter().map(|idx| rewritten[*idx].cost(&shared.cost_fn)).min().unwrap()
             ).sum::<i32>();
@@ -1444,24 +1633,31 @@ pub struct CompressionStepResult {
     pub uses: i32,
     pub use_exprs: Vec<Idx>,
     pub use_args: Vec<Vec<Idx>>,
+    pub use_counts: Vec<i32>, // number of times each of `use_exprs` appears in the corpus
     pub dc_inv_str: String,
     pub initial_cost: i32,
     pub name_mapping: Vec<(String,String)>,
     pub dc_comparison_millis: Option<usize>,
+    pub num_tasks: usize, // number of distinct tasks the abstraction is used in
+    pub stats: Stats, // search stats of the step that found this abstraction
+    pub timings: StepTimings,
+    pub memory: StepMemory,
 }
 
 impl CompressionStepResult {
-    fn new(done: FinishedPattern, inv_name: &str, shared: &mut SharedData, very_first_cost: i32, name_mapping: &[(String,String)], dc_comparison_millis: Option<usize>) -> Self {
+    fn new(done: FinishedPattern, inv_name: &str, shared: &mut SharedData, very_first_cost: i32, name_mapping: &[(String,String)], dc_comparison_millis: Option<usize>, timings: &StepTimings) -> Result<Self, StitchError> {
 
         let inv = done.to_invention(inv_name, shared);
-        let rewritten = rewrite_fast(&done, shared, &Node::Prim(inv.name.clone().into()), &shared.cost_fn);
+        let tstart = std::time::Instant::now();
+        let rewritten = rewrite_fast(&done, shared, &Node::Prim(inv.name.clone().into()), &shared.cost_fn)?;
+        let timings = StepTimings { rewrite_millis: tstart.elapsed().as_millis() as usize, ..timings.clone() };
 
         let expected_cost = shared.init_cost_weighted - done.compressive_utility;
         // let final_cost = rewritten.cost();
         let final_cost = shared.root_idxs_of_task.iter().map(|root_idxs|
             root_idxs.iter().map(|idx| (rewritten[*idx].cost(&shared.cost_fn) as f32 * shared.weight_by_root_idx[*idx]).round() as i32).min().unwrap()
         ).sum::<i32>();
-        if expected_cost != final_cost && !shared.cfg.quiet { println!("*** expected cost {expected_cost} != final cost {final_cost}") }
+        if expected_cost != final_cost && !shared.cfg.quiet { warn!(target: "stitch::rewrite", "*** expected cost {expected_cost} != final cost {final_cost}") }
         let multiplier = shared.init_cost_weighted as f64 / final_cost as f64;
         let multiplier_wrt_orig = very_first_cost as f64 / final_cost as f64;
         let uses = done.usages;
@@ -1470,39 +1666,42 @@ impl CompressionStepResult {
             done.pattern.first_zid_of_ivar.iter().map(|zid|
                 shared.arg_of_zid_node[*zid][node].shifted_id
             ).collect()).collect();
+        let use_counts: Vec<i32> = use_exprs.iter().map(|node| shared.num_paths_to_node[*node]).collect();
         
 
         // dreamcoder compatability
         let dc_inv_str: String = dc_inv_str(&inv, name_mapping);
-        // Rewrite to dreamcoder syntax with all past invention
-        // we rewrite "inv1)" and "inv1 " instead of just "inv1" because we dont want to match on "inv10"
 
         // Combine the past_invs with the existing dreamcoder inventions.
         let mut name_mapping = name_mapping.to_vec();
         name_mapping.push((inv.name.clone(), dc_inv_str.clone()));
-        
 
+        // Rewrite to dreamcoder syntax with all past inventions and the current one inlined
         let rewritten_dreamcoder: Option<Vec<String>> = if !shared.cfg.rewritten_dreamcoder { None } else {
-            Some(rewritten.iter().map(|p|{
-            let mut res: String = p.to_string();
-            for (name, anonymous) in &name_mapping {
-                res = replace_prim_with(&res, name, anonymous);
-            }
+            Some(rewritten.iter().map(|p| dreamcoder_string(p.immut(), &name_mapping)).collect())
+        };
 
-            // Now go ahead and replace the current invention.
-            res = replace_prim_with(&res, inv_name, &dc_inv_str);
-            res = res.replace("(lam ","(lambda ");
-            res
-        }).collect())};
+        let num_tasks = use_exprs.iter().flat_map(|node| shared.tasks_of_node[*node].iter()).collect::<FxHashSet<_>>().len();
+        let stats = shared.stats.lock().clone();
+        let memory = StepMemory {
+            peak_rss_kb: peak_rss_kb(),
+            expr_set: shared.set.len(),
+            zip_of_zid: shared.zip_of_zid.len(),
+            arg_of_zid_node: shared.arg_of_zid_node.iter().map(|args| args.len()).sum(),
+            worklist_high_water: shared.crit.lock().worklist_high_water,
+        };
 
-        CompressionStepResult { set: shared.set.clone(), inv, rewritten, rewritten_dreamcoder, done, expected_cost, final_cost, multiplier, multiplier_wrt_orig, uses, use_exprs, use_args, dc_inv_str, initial_cost: shared.init_cost, name_mapping, dc_comparison_millis }
+        Ok(CompressionStepResult { set: shared.set.clone(), inv, rewritten, rewritten_dreamcoder, done, expected_cost, final_cost, multiplier, multiplier_wrt_orig, uses, use_exprs, use_args, use_counts, dc_inv_str, initial_cost: shared.init_cost, name_mapping, dc_comparison_millis, num_tasks, stats, timings, memory })
     }
     pub fn json(&self, cfg: &CompressionStepConfig) -> serde_json::Value {        
-        let all_uses: Vec<serde_json::Value> = {
-            let use_exprs: Vec<String> = self.use_exprs.iter().map(|expr| self.set.get(*expr).to_string()).collect();
-            let use_args: Vec<String> = self.use_args.iter().map(|args| format!("{} {}", self.inv.name, args.iter().map(|expr| self.set.get(*expr).to_string()).collect::<Vec<String>>().join(" "))).collect();
-            use_exprs.iter().zip(use_args.iter()).sorted().map(|(expr,args)| json!({args: expr})).collect()
-        };
+        // each use as (the expr it matched, the call that replaced it, the args it was called with, how often it appears)
+        let uses: Vec<(String, String, Vec<String>, i32)> = self.use_exprs.iter().zip(self.use_args.iter()).zip(self.use_counts.iter()).map(|((expr, args), count)| {
+            let args: Vec<String> = args.iter().map(|arg| self.set.get(*arg).to_string()).collect();
+            (self.set.get(*expr).to_string(), format!("{} {}", self.inv.name, args.join(" ")), args, *count)
+        }).sorted_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1))).collect();
+        let all_uses: Vec<serde_json::Value> = uses.iter().map(|(expr, call, _, _)| json!({call: expr})).collect();
+        let use_args: Vec<&Vec<String>> = uses.iter().map(|(_, _, args, _)| args).collect();
+        let use_counts: Vec<i32> = uses.iter().map(|(_, _, _, count)| *count).collect();
 
         let rewritten = if !cfg.rewritten_intermediates { None } else { Some(self.rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>()) };
         let rewritten_dreamcoder = if !cfg.rewritten_intermediates { &None } else { &self.rewritten_dreamcoder };
@@ -1520,7 +1719,12 @@ impl CompressionStepResult {
             "rewritten": rewritten,
             "rewritten_dreamcoder": rewritten_dreamcoder,
             "uses": all_uses,
-            "dc_comparison_millis": self.dc_comparison_millis
+            "use_args": use_args,
+            "use_counts": use_counts,
+            "dc_comparison_millis": self.dc_comparison_millis,
+            "stats": self.stats,
+            "timings": self.timings,
+            "memory": self.memory,
         })
     }
 }
@@ -1633,7 +1837,7 @@ fn get_utility_of_loc_once(pattern: &Pattern, shared: &SharedData) -> Vec<i32> {
     pattern.match_locations.iter().map(|loc| {
 
         //  if there are any free ivars in the arg at this location then we can't apply this invention here so *total* util should be 0
-        for (_ivar,zid) in pattern.first_zid_of_ivar.iter().enumerate() {
+        for zid in pattern.first_zid_of_ivar.iter() {
             let shifted_arg = shared.arg_of_zid_node[*zid][loc].shifted_id;
             if !shared.analyzed_ivars[shifted_arg].is_empty() {
                 return 0; // set whole util to 0 for this loc, causing an autoreject
@@ -1818,16 +2022,23 @@ fn use_counts(pattern: &Pattern, zip_of_zid: &[Vec<ZNode>], arg_of_zid_node: &[F
 }
 
 /// Multistep compression
+#[allow(clippy::too_many_arguments)]
 pub fn multistep_compression_internal(
     train_programs: &[ExprOwned],
     tasks: Option<Vec<String>>,
     weights: Option<Vec<f32>>,
     name_mapping: Option<Vec<(String, String)>>,
+    inventions: Option<&[Invention]>,
     follow: Option<Vec<Invention>>,
+    resume: Option<&Checkpoint>,
+    observer: Option<Arc<dyn Observer>>,
     cfg: &MultistepCompressionConfig
-) -> Vec<CompressionStepResult> {
+) -> Result<Vec<CompressionStepResult>, StitchError> {
+    cfg.validate()?;
 
-    let mut rewritten: Vec<ExprOwned> = train_programs.to_vec();
+    // when resuming we pick up from the checkpoint's rewritten programs, but costs are still relative to `train_programs`
+    let mut rewritten: Vec<ExprOwned> = resume.map(|checkpoint| checkpoint.rewritten.clone()).unwrap_or_else(|| train_programs.to_vec());
+    let num_resumed: usize = resume.map(|checkpoint| checkpoint.abstractions.len()).unwrap_or(0);
     let mut step_results: Vec<CompressionStepResult> = Default::default();
     let cost_fn = &cfg.step.cost.expr_cost();
 
@@ -1836,7 +2047,9 @@ pub fn multistep_compression_internal(
     let mut cfg = cfg.clone();
 
     if let Some(follow) = &follow {
-        assert_eq!(follow.len(), cfg.iterations);
+        if follow.len() != cfg.iterations {
+            return Err(StitchError::InvalidInput(format!("following {} abstractions but running {} iterations", follow.len(), cfg.iterations)))
+        }
         cfg.step.follow_prune = true;
         cfg.step.rewrite_check = false; // this will cause a loop
         if !cfg.verbose_rewrite{
@@ -1860,13 +2073,17 @@ pub fn multistep_compression_internal(
 
 
 
-    for i in 0..cfg.iterations {
-        if !cfg.step.quiet { println!("{}",format!("\n=======Iteration {i}=======").blue().bold()) }
+    // `iterations` counts the resumed abstractions too, so rerunning the same command with --resume finishes the original run
+    for i in num_resumed..cfg.iterations {
+        if cfg.cancel.is_cancelled() {
+            return Err(StitchError::Cancelled)
+        }
+        if !cfg.step.quiet { info!(target: "stitch::multistep", "{}",format!("\n=======Iteration {i}=======").blue().bold()) }
         let inv_name = if let Some(follow) = &follow {
             cfg.step.follow = Some(follow[i].body.to_string());
             follow[i].name.clone()
         } else {
-            format!("{}{}", cfg.abstraction_prefix, cfg.previous_abstractions + step_results.len())
+            format!("{}{}", cfg.abstraction_prefix, cfg.previous_abstractions + num_resumed + step_results.len())
         };
 
         // call actual compression
@@ -1878,47 +2095,58 @@ pub fn multistep_compression_internal(
             &weights,
             very_first_cost,
             &name_mapping,
-            );
+            i,
+            observer.clone(),
+            )?;
 
         if !res.is_empty() {
             // rewrite with the invention
             let res: CompressionStepResult = res[0].clone();
             rewritten = res.rewritten.clone();
             name_mapping = res.name_mapping.clone();
-            if !cfg.step.quiet { println!("Chose Invention {}: {}", res.inv.name, res) }
+            if !cfg.step.quiet { info!(target: "stitch::multistep", "Chose Invention {}: {}", res.inv.name, res) }
+            if let Some(observer) = &observer {
+                observer.iteration_finished(i, &res);
+            }
             step_results.push(res);
+            if let Some(checkpoint_path) = &cfg.checkpoint {
+                let json_res = json_of_run(&step_results, resume, train_programs, &Some(weights.clone()), &Some(tasks.clone()), &name_mapping, inventions, cost_fn, &cfg, true);
+                write_checkpoint(checkpoint_path, &json_res)?;
+                if !cfg.step.quiet { info!(target: "stitch::multistep", "Wrote checkpoint to {checkpoint_path:?}") }
+            }
         } else if follow.is_some() {
             // if `follow` was given then we will keep going for the full set of iterations
-            if !cfg.step.quiet { println!("Invention not found: {}", cfg.step.follow.as_ref().unwrap() ) }
+            if !cfg.step.quiet { info!(target: "stitch::multistep", "Invention not found: {}", cfg.step.follow.as_ref().unwrap() ) }
         } else {
-            if !cfg.step.quiet { println!("No inventions found at iteration {i}") }
+            if !cfg.step.quiet { info!(target: "stitch::multistep", "No inventions found at iteration {i}") }
             break;    
         }
     }
 
     if cfg.step.show_rewritten {
-        println!("rewritten:\n{}", rewritten.iter().map(|p|p.to_string()).collect::<Vec<_>>().join("\n"));
+        info!(target: "stitch::multistep", "rewritten:\n{}", rewritten.iter().map(|p|p.to_string()).collect::<Vec<_>>().join("\n"));
     }
 
-    if !cfg.step.quiet { println!("{}","\n=======Compression Summary=======".blue().bold()) }
-    if !cfg.step.quiet { println!("Found {} inventions", step_results.len()) }
+    if !cfg.step.quiet { info!(target: "stitch::multistep", "{}","\n=======Compression Summary=======".blue().bold()) }
+    if !cfg.step.quiet { info!(target: "stitch::multistep", "Found {} inventions", step_results.len()) }
     let rewritten_cost = min_cost(&rewritten, &Some(weights.clone()), &Some(tasks.clone()), cost_fn);
-    if !cfg.step.quiet { println!("Cost Improvement: ({:.2}x better) {} -> {}", compression_factor(very_first_cost, rewritten_cost), very_first_cost, rewritten_cost) }
+    if !cfg.step.quiet { info!(target: "stitch::multistep", "Cost Improvement: ({:.2}x better) {} -> {}", compression_factor(very_first_cost, rewritten_cost), very_first_cost, rewritten_cost) }
     for res in step_results.iter() {
         let rewritten_cost = min_cost(&res.rewritten, &Some(weights.clone()), &Some(tasks.clone()), cost_fn);
-        if !cfg.step.quiet { println!("{} ({:.2}x wrt orig): {}" , res.inv.name.clone().blue(), compression_factor(very_first_cost, rewritten_cost), res) }
+        if !cfg.step.quiet { info!(target: "stitch::multistep", "{} ({:.2}x wrt orig): {}" , res.inv.name.clone().blue(), compression_factor(very_first_cost, rewritten_cost), res) }
     }
-    if !cfg.step.quiet { println!("Time: {}ms", tstart.elapsed().as_millis()) }
+    if !cfg.step.quiet { info!(target: "stitch::multistep", "Time: {}ms", tstart.elapsed().as_millis()) }
     if cfg.step.follow_prune && !(
         cfg.step.no_opt_upper_bound
         && cfg.step.no_opt_force_multiuse
         && cfg.step.no_opt_useless_abstract
-        && cfg.step.no_opt_arity_zero) && !cfg.step.quiet { println!("{} you often want to run --follow-track with --no-opt otherwise your target may get pruned", "[WARNING]".yellow()) }
+        && cfg.step.no_opt_arity_zero) && !cfg.step.quiet { warn!(target: "stitch::multistep", "{} you often want to run --follow-track with --no-opt otherwise your target may get pruned", "[WARNING]".yellow()) }
 
-    step_results
+    Ok(step_results)
 }
 
 /// Takes a set of programs and does one full step of compresison.
+#[allow(clippy::too_many_arguments)]
 pub fn compression_step(
     programs: &[ExprOwned],
     new_inv_name: &str, // name of the new invention, like "inv4"
@@ -1927,7 +2155,9 @@ pub fn compression_step(
     weights: &[f32],
     very_first_cost: i32,
     name_mapping: &[(String, String)],
-) -> Vec<CompressionStepResult> {
+    iteration: usize,
+    observer: Option<Arc<dyn Observer>>,
+) -> Result<Vec<CompressionStepResult>, StitchError> {
 
     let cfg = &multistep_cfg.step.clone();
 
@@ -1949,7 +2179,7 @@ pub fn compression_step(
     // a node participates in (ie multiple uses within a single program or among programs)
     let (num_paths_to_node, num_paths_to_node_by_root_idx) : (Vec<i32>, Vec<Vec<i32>>) = num_paths_to_node(&roots, &corpus_span, &set);
 
-    if !cfg.quiet { println!("num_paths_to_node(): {:?}ms", tstart.elapsed().as_millis()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "num_paths_to_node(): {:?}ms", tstart.elapsed().as_millis()) }
     tstart = std::time::Instant::now();
 
     let mut task_name_of_task: Vec<String> = vec![];
@@ -1968,7 +2198,7 @@ pub fn compression_step(
     let tasks_of_node: Vec<FxHashSet<usize>> = associate_tasks(&roots, &set, &corpus_span, &task_of_root_idx);
 
     let init_cost_by_root_idx: Vec<i32> = roots.iter().map(|idx| analyzed_cost[*idx]).collect();
-    let init_cost_by_root_idx_weighted: Vec<f32> = init_cost_by_root_idx.iter().zip(weights.iter()).map(|(cost,weight)| (*cost as f32 * weight)).collect();
+    let init_cost_by_root_idx_weighted: Vec<f32> = init_cost_by_root_idx.iter().zip(weights.iter()).map(|(cost,weight)| *cost as f32 * weight).collect();
     let init_cost: i32 = root_idxs_of_task.iter().map(|root_idxs|
         root_idxs.iter().map(|idx| init_cost_by_root_idx[*idx]).min().unwrap()
     ).sum();
@@ -1977,9 +2207,9 @@ pub fn compression_step(
     ).sum();
     let first_train_cost = roots.iter().map(|idx| analyzed_cost[*idx]).sum(); // This is used for --verbose-print
 
-    if !cfg.quiet { println!("associate_tasks() and other task stuff: {:?}ms", tstart.elapsed().as_millis()) }
-    if !cfg.quiet { println!("num unique tasks: {}", task_name_of_task.len()) }
-    if !cfg.quiet { println!("num unique programs: {}", roots.len()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "associate_tasks() and other task stuff: {:?}ms", tstart.elapsed().as_millis()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "num unique tasks: {}", task_name_of_task.len()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "num unique programs: {}", roots.len()) }
     tstart = std::time::Instant::now();
     
     // cost of a single usage times number of paths to node
@@ -1987,7 +2217,7 @@ pub fn compression_step(
 
     let mut analyzed_free_vars = AnalyzedExpr::new(FreeVarAnalysis);
 
-    if !cfg.quiet { println!("cost_of_node structs: {:?}ms", tstart.elapsed().as_millis()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "cost_of_node structs: {:?}ms", tstart.elapsed().as_millis()) }
     tstart = std::time::Instant::now();
 
     let (zid_of_zip,
@@ -1996,23 +2226,24 @@ pub fn compression_step(
         zids_of_node,
         extensions_of_zid) = get_zippers(&corpus_span, &analyzed_cost, &mut set, &mut analyzed_free_vars);
     
-    if !cfg.quiet { println!("get_zippers(): {:?}ms", tstart.elapsed().as_millis()) }
+    let mut timings = StepTimings { zippers_millis: tstart.elapsed().as_millis() as usize, ..Default::default() };
+    if !cfg.quiet { debug!(target: "stitch::prep", "get_zippers(): {:?}ms", timings.zippers_millis) }
     tstart = std::time::Instant::now();
     
-    if !cfg.quiet { println!("{} zips", zip_of_zid.len()) }
-    if !cfg.quiet { println!("arg_of_zid_node size: {}", arg_of_zid_node.len()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "{} zips", zip_of_zid.len()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "arg_of_zid_node size: {}", arg_of_zid_node.len()) }
 
     // set up tracking if any
     let tracking: Option<Tracking> = {
         if let Some(s) = &cfg.follow {
             let mut set = ExprSet::empty(Order::ChildFirst, false, false);
-            let idx = set.parse_extend(s).unwrap();
+            let idx = set.parse_extend(s).map_err(|e| StitchError::parse(None, s, e))?;
             let expr = ExprOwned::new(set,idx);
             if let Some(zids_of_ivar) = zids_of_ivar_of_expr(&expr, &zid_of_zip) {
                 Some(Tracking { expr, zids_of_ivar })
             } else {
-                if !cfg.quiet { println!("Tracking: can't possibly find a match for this in corpus because one if the necessary zippers ZIDs doesnt exist in corpus")}
-                return vec![];
+                if !cfg.quiet { info!(target: "stitch::track", "Tracking: can't possibly find a match for this in corpus because one if the necessary zippers ZIDs doesnt exist in corpus")}
+                return Ok(vec![]);
             }
         } else {
             None
@@ -2021,7 +2252,7 @@ pub fn compression_step(
     
 
 
-    if !cfg.quiet { println!("Tracking setup: {:?}ms", tstart.elapsed().as_millis()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "Tracking setup: {:?}ms", tstart.elapsed().as_millis()) }
     tstart = std::time::Instant::now();
 
     let mut analyzed_ivars = AnalyzedExpr::new(IVarAnalysis);
@@ -2030,7 +2261,8 @@ pub fn compression_step(
     analyzed_ivars.analyze(&set);
 
 
-    if !cfg.quiet { println!("ran analyses: {:?}ms", tstart.elapsed().as_millis()) }
+    timings.analyses_millis = tstart.elapsed().as_millis() as usize;
+    if !cfg.quiet { debug!(target: "stitch::prep", "ran analyses: {:?}ms", timings.analyses_millis) }
     tstart = std::time::Instant::now();
 
 
@@ -2039,7 +2271,7 @@ pub fn compression_step(
     // define all the important data structures for compression
     let mut donelist: Vec<FinishedPattern> = Default::default(); // completed inventions will go here    
 
-    let single_hole = Pattern::single_hole(&corpus_span, &cost_of_node_all, &num_paths_to_node, &set, cost_fn, cfg);
+    let single_hole = Pattern::single_hole(&corpus_span, &cost_of_node_all, &num_paths_to_node, &set, cost_fn, cfg)?;
 
     let mut azero_pruning_cutoff = 0;
 
@@ -2125,10 +2357,11 @@ pub fn compression_step(
         }
     }
 
-    if !cfg.quiet { println!("arity 0: {:?}ms", tstart.elapsed().as_millis()) }
+    timings.arity_zero_millis = tstart.elapsed().as_millis() as usize;
+    if !cfg.quiet { debug!(target: "stitch::prep", "arity 0: {:?}ms", timings.arity_zero_millis) }
     tstart = std::time::Instant::now();
 
-    if !cfg.quiet { println!("got {} arity zero inventions", donelist.len()) }
+    if !cfg.quiet { debug!(target: "stitch::prep", "got {} arity zero inventions", donelist.len()) }
 
     let mut worklist = BinaryHeap::new();
     worklist.push(HeapItem::new(single_hole));
@@ -2176,9 +2409,12 @@ pub fn compression_step(
         multistep_cfg: multistep_cfg.clone(),
         tracking,
         fused_lambda_tags: fused_copy,
+        rewrite_check_error: Mutex::new(None),
+        iteration,
+        observer,
     });
 
-    if !shared.cfg.quiet { println!("built SharedData: {:?}ms", tstart.elapsed().as_millis()) }
+    if !shared.cfg.quiet { debug!(target: "stitch::prep", "built SharedData: {:?}ms", tstart.elapsed().as_millis()) }
     tstart = std::time::Instant::now();
 
     if cfg.verbose_best {
@@ -2188,13 +2424,22 @@ pub fn compression_step(
             let best_expr: String = crit.deref_mut().donelist.first().unwrap().info(&shared);
             let new_expected_cost = first_train_cost - crit.donelist.first().unwrap().compressive_utility + crit.donelist.first().unwrap().to_expr(&shared).cost(&shared.cost_fn);
             let trainratio = first_train_cost as f64/new_expected_cost as f64;
-            if !shared.cfg.quiet { println!("{} @ step=0 util={} trainratio={:.2} for {}", "[new best utility]".blue(), best_util, trainratio, best_expr) }
+            if !shared.cfg.quiet { info!(target: "stitch::search", "{} @ step=0 util={} trainratio={:.2} for {}", "[new best utility]".blue(), best_util, trainratio, best_expr) }
         }
     }
 
-    if !shared.cfg.quiet { println!("TOTAL PREP: {:?}ms", tstart_prep.elapsed().as_millis()) }
+    timings.prep_millis = tstart_prep.elapsed().as_millis() as usize;
+    if !shared.cfg.quiet { info!(target: "stitch::prep", "TOTAL PREP: {:?}ms", timings.prep_millis) }
 
-    if !shared.cfg.quiet { println!("running pattern search...") }
+    if let Some(observer) = &shared.observer {
+        observer.prep_finished(iteration, &timings);
+        // the best arity zero abstraction is where the search starts from
+        if let Some(best) = shared.crit.lock().donelist.first() {
+            observer.new_best(iteration, best.utility, &best.to_expr(&shared).to_string());
+        }
+    }
+
+    if !shared.cfg.quiet { info!(target: "stitch::search", "running pattern search...") }
 
     // *****************
     // * STITCH SEARCH *
@@ -2220,8 +2465,13 @@ pub fn compression_step(
         }
     }
 
-    if !shared.cfg.quiet { println!("TOTAL SEARCH: {:?}ms", tstart.elapsed().as_millis()) }
-    if !shared.cfg.quiet { println!("TOTAL PREP + SEARCH: {:?}ms", tstart_total.elapsed().as_millis()) }
+    if multistep_cfg.cancel.is_cancelled() {
+        return Err(StitchError::Cancelled)
+    }
+
+    timings.search_millis = tstart.elapsed().as_millis() as usize;
+    if !shared.cfg.quiet { info!(target: "stitch::search", "TOTAL SEARCH: {:?}ms", timings.search_millis) }
+    if !shared.cfg.quiet { info!(target: "stitch::search", "TOTAL PREP + SEARCH: {:?}ms", tstart_total.elapsed().as_millis()) }
 
 
     tstart = std::time::Instant::now();
@@ -2232,18 +2482,22 @@ pub fn compression_step(
     // one last .update()
     shared.crit.lock().deref_mut().update(cfg);
 
-    if !shared.cfg.quiet { println!("{:?}", shared.stats.lock().deref_mut()) }
+    if !shared.cfg.quiet { debug!(target: "stitch::search", "{:?}", shared.stats.lock().deref_mut()) }
     assert!(shared.crit.lock().deref_mut().worklist.is_empty());
 
+    if let Some(e) = shared.rewrite_check_error.lock().take() {
+        return Err(e)
+    }
+
     let donelist: Vec<FinishedPattern> = shared.crit.lock().deref_mut().donelist.clone();
 
     let dc_comparison_millis = if cfg.dreamcoder_comparison {
-        if !shared.cfg.quiet { println!("Timing point 1 (from the start of compression_step to final donelist): {:?}ms", tstart_total.elapsed().as_millis()) }
-        if !shared.cfg.quiet { println!("Timing Comparison Point A (search) (millis): {}", tstart_total.elapsed().as_millis()) }
+        if !shared.cfg.quiet { info!(target: "stitch::search", "Timing point 1 (from the start of compression_step to final donelist): {:?}ms", tstart_total.elapsed().as_millis()) }
+        if !shared.cfg.quiet { info!(target: "stitch::search", "Timing Comparison Point A (search) (millis): {}", tstart_total.elapsed().as_millis()) }
         let tstart_rewrite = std::time::Instant::now();
-        rewrite_fast(&donelist[0], &shared, &Node::Prim(new_inv_name.into()), cost_fn);
-        if !shared.cfg.quiet { println!("Timing point 2 (rewriting the candidate): {:?}ms", tstart_rewrite.elapsed().as_millis()) }
-        if !shared.cfg.quiet { println!("Timing Comparison Point B (search+rewrite) (millis): {}", tstart_total.elapsed().as_millis()) }
+        rewrite_fast(&donelist[0], &shared, &Node::Prim(new_inv_name.into()), cost_fn)?;
+        if !shared.cfg.quiet { info!(target: "stitch::search", "Timing point 2 (rewriting the candidate): {:?}ms", tstart_rewrite.elapsed().as_millis()) }
+        if !shared.cfg.quiet { info!(target: "stitch::search", "Timing Comparison Point B (search+rewrite) (millis): {}", tstart_total.elapsed().as_millis()) }
         Some(tstart_total.elapsed().as_millis() as usize)
     } else {
         None
@@ -2252,10 +2506,10 @@ pub fn compression_step(
     let mut results: Vec<CompressionStepResult> = vec![];
 
     // construct CompressionStepResults and print some info about them)
-    if !shared.cfg.quiet { println!("Cost before: {}", shared.init_cost) }
+    if !shared.cfg.quiet { debug!(target: "stitch::search", "Cost before: {}", shared.init_cost) }
     for (i,done) in donelist.iter().enumerate() {
-        let res = CompressionStepResult::new(done.clone(), new_inv_name, &mut shared, very_first_cost, name_mapping, dc_comparison_millis);
-        if !shared.cfg.quiet { println!("{i}: {res}") }
+        let res = CompressionStepResult::new(done.clone(), new_inv_name, &mut shared, very_first_cost, name_mapping, dc_comparison_millis, &timings)?;
+        if !shared.cfg.quiet { debug!(target: "stitch::search", "{i}: {res}") }
         results.push(res);
     }
 
@@ -2265,26 +2519,70 @@ pub fn compression_step(
         }
     }
 
-    if !shared.cfg.quiet { println!("post processing: {:?}ms", tstart.elapsed().as_millis()) }
+    if !shared.cfg.quiet { debug!(target: "stitch::search", "post processing: {:?}ms", tstart.elapsed().as_millis()) }
 
-    results
+    Ok(results)
 }
 
-/// toplevel entrypoint to compression used by most apis
+/// toplevel entrypoint to compression used by most apis. `observer` gets progress events as compression runs, see [observer.rs].
+/// `inventions` are abstractions that already exist in the input, like those of a DreamCoder DSL, which the programs use
+/// by name. They're listed as `prior_abstractions` in the output json, and `name_mapping` has their #(lambda ...) forms.
+/// Logs go to the console unless a logger was installed first, see [logging.rs].
+#[allow(clippy::too_many_arguments)]
 pub fn multistep_compression(
     programs: &[String],
     tasks: Option<Vec<String>>,
     weights: Option<Vec<f32>>,
     name_mapping: Option<Vec<(String,String)>>,
+    inventions: Option<Vec<Invention>>,
     follow: Option<Vec<Invention>>,
+    observer: Option<Arc<dyn Observer>>,
     cfg: &MultistepCompressionConfig
-)-> (Vec<CompressionStepResult>, serde_json::Value) {
-    let mut programs = programs.to_vec();
+)-> Result<(Vec<CompressionStepResult>, serde_json::Value), StitchError> {
+    init_default_logging();
+    check_lengths(programs.len(), &tasks, &weights)?;
+
+    // parse the program strings into expressions, checking all of them before giving up so every bad one gets reported
+    let (parsed, invalid_programs) = parse_programs(programs, tasks.as_deref());
+    if !invalid_programs.is_empty() {
+        if !cfg.skip_invalid {
+            return Err(StitchError::InvalidPrograms(invalid_programs))
+        }
+        if parsed.is_empty() {
+            return Err(StitchError::InvalidInput(format!("all {} programs failed to parse", programs.len())))
+        }
+        if !cfg.silent { warn!(target: "stitch::multistep", "{} skipping {} programs that failed to parse", "[WARNING]".yellow(), invalid_programs.len()) }
+    }
+    // tasks and weights follow their programs through skipping
+    let tasks: Option<Vec<String>> = tasks.map(|tasks| parsed.iter().map(|(i,_)| tasks[*i].clone()).collect());
+    let weights: Option<Vec<f32>> = weights.map(|weights| parsed.iter().map(|(i,_)| weights[*i]).collect());
+    let train_programs: Vec<ExprOwned> = parsed.into_iter().map(|(_,e)| e).collect();
+
+    let (step_results, mut json_res) = multistep_compression_parsed(&train_programs, tasks, weights, name_mapping, inventions, follow, observer, cfg)?;
+    if cfg.skip_invalid {
+        json_res["invalid_programs"] = json!(invalid_programs);
+    }
+
+    Ok((step_results, json_res))
+}
+
+/// `multistep_compression` on programs that were already parsed, e.g. a corpus that is kept around to compress many
+/// times. `cfg.skip_invalid` has no effect here since every program is valid.
+#[allow(clippy::too_many_arguments)]
+pub fn multistep_compression_parsed(
+    programs: &[ExprOwned],
+    tasks: Option<Vec<String>>,
+    weights: Option<Vec<f32>>,
+    name_mapping: Option<Vec<(String,String)>>,
+    inventions: Option<Vec<Invention>>,
+    follow: Option<Vec<Invention>>,
+    observer: Option<Arc<dyn Observer>>,
+    cfg: &MultistepCompressionConfig
+)-> Result<(Vec<CompressionStepResult>, serde_json::Value), StitchError> {
+    init_default_logging();
     let mut cfg = cfg.clone();
 
-    if let Some(tasks) = &tasks {
-        assert_eq!(tasks.len(), programs.len());
-    }
+    check_lengths(programs.len(), &tasks, &weights)?;
 
     if cfg.silent {
         cfg.step.quiet = true
@@ -2293,27 +2591,30 @@ pub fn multistep_compression(
     if cfg.no_opt {
         cfg.step.no_opt();
     }
-    
-    if cfg.shuffle {
-        programs.shuffle(&mut rand::thread_rng());
-    }
-    if let Some(n) = cfg.truncate {
-        programs.truncate(n);
-    }
-    
-    // parse the program strings into expressions
-    let train_programs: Vec<ExprOwned> = programs.iter().map(|p|{
-        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
-        let idx = set.parse_extend(p).unwrap();
-        ExprOwned::new(set,idx)
-    }).collect();
+
+    // tasks and weights follow their programs through shuffling and truncation, and the programs are only copied if
+    // they get reordered
+    let (train_programs, tasks, weights): (Cow<[ExprOwned]>, _, _) = if cfg.shuffle || cfg.truncate.is_some() {
+        let mut order: Vec<usize> = (0..programs.len()).collect();
+        if cfg.shuffle {
+            order.shuffle(&mut rand::thread_rng());
+        }
+        if let Some(n) = cfg.truncate {
+            order.truncate(n);
+        }
+        (Cow::Owned(order.iter().map(|i| programs[*i].clone()).collect()),
+         tasks.map(|tasks| order.iter().map(|i| tasks[*i].clone()).collect()),
+         weights.map(|weights| order.iter().map(|i| weights[*i]).collect()))
+    } else {
+        (Cow::Borrowed(programs), tasks, weights)
+    };
 
     let cost_fn = cfg.step.cost.expr_cost();
 
     if !cfg.silent {
-        println!("{}","**********".blue().bold());
-        println!("{}","* Stitch *".blue().bold());
-        println!("{}","**********".blue().bold());
+        info!(target: "stitch::multistep", "{}","**********".blue().bold());
+        info!(target: "stitch::multistep", "{}","* Stitch *".blue().bold());
+        info!(target: "stitch::multistep", "{}","**********".blue().bold());
         programs_info(&train_programs, &cost_fn);
     }
 
@@ -2321,38 +2622,202 @@ pub fn multistep_compression(
         &train_programs, 
         tasks.clone(),
         weights.clone(),
-        name_mapping, 
+        name_mapping.clone(), 
+        inventions.as_deref(),
         follow,
+        None,
+        observer,
         &cfg, 
-    );
+    )?;
 
     // write everything to json
-    let json_res = json_of_step_results(&step_results, &train_programs, weights, tasks, &cost_fn, &cfg);
+    let json_res = json_of_step_results(&step_results, &train_programs, weights, tasks, &name_mapping.unwrap_or_default(), inventions.as_deref(), &cost_fn, &cfg);
 
-    (step_results, json_res)
+    Ok((step_results, json_res))
 }
 
-pub fn json_of_step_results(step_results: &[CompressionStepResult], train_programs: &Vec<ExprOwned>, weights: Option<Vec<f32>>, tasks: Option<Vec<String>>, cost_fn: &ExprCost, cfg: &MultistepCompressionConfig) -> serde_json::Value {
-    let rewritten: &Vec<ExprOwned> = step_results.iter().last().map(|res| &res.rewritten).unwrap_or(train_programs);
-    let original_cost = min_cost(train_programs, &weights, &tasks, cost_fn);
-    let final_cost = min_cost(rewritten, &weights, &tasks, cost_fn);
-    let rewritten = step_results.iter().last().map(|res| &res.rewritten).unwrap_or(train_programs).iter().map(|p| p.to_string()).collect::<Vec<String>>();
-    let rewritten_dreamcoder = if !cfg.step.rewritten_dreamcoder { None } else {
-        let rewritten_dreamcoder = step_results.iter().last().map(|res| res.rewritten_dreamcoder.clone().unwrap()).unwrap_or_else(||train_programs.iter().map(
-            |p| p.to_string().replace("(lam ", "(lambda ").replace("(lam_", "(lambda_")
-        ).collect::<Vec<String>>());
-        Some(rewritten_dreamcoder)
-    };
-    json!({
+/// one task and weight per program, if there are any
+fn check_lengths(num_programs: usize, tasks: &Option<Vec<String>>, weights: &Option<Vec<f32>>) -> Result<(), StitchError> {
+    if let Some(tasks) = tasks {
+        if tasks.len() != num_programs {
+            return Err(StitchError::InvalidInput(format!("got {} tasks for {} programs", tasks.len(), num_programs)))
+        }
+    }
+    if let Some(weights) = weights {
+        if weights.len() != num_programs {
+            return Err(StitchError::InvalidInput(format!("got {} weights for {} programs", weights.len(), num_programs)))
+        }
+    }
+    Ok(())
+}
+
+/// Resume multistep compression from a checkpoint (the output json of an earlier, possibly interrupted, run).
+/// Compression continues from the checkpoint's rewritten programs until `cfg.iterations` abstractions have been
+/// found in total, and the returned json covers both the resumed abstractions and the new ones. The returned
+/// `CompressionStepResult`s only cover the new ones. `tasks`, `weights`, `name_mapping`, `inventions` and `observer` are the same as for
+/// `multistep_compression`, with `tasks` and `weights` referring to the checkpoint's original programs, so they can't be
+/// given if the earlier run shuffled, truncated or skipped invalid programs.
+pub fn multistep_compression_resume(
+    checkpoint: &Checkpoint,
+    tasks: Option<Vec<String>>,
+    weights: Option<Vec<f32>>,
+    name_mapping: Option<Vec<(String,String)>>,
+    inventions: Option<Vec<Invention>>,
+    observer: Option<Arc<dyn Observer>>,
+    cfg: &MultistepCompressionConfig
+)-> Result<(Vec<CompressionStepResult>, serde_json::Value), StitchError> {
+    init_default_logging();
+    let mut cfg = cfg.clone();
+
+    if cfg.shuffle || cfg.truncate.is_some() || cfg.skip_invalid {
+        return Err(StitchError::InvalidInput("shuffle, truncate and skip_invalid can't be used when resuming, the checkpoint's programs are used as they are".to_string()))
+    }
+    if checkpoint.reordered && (tasks.is_some() || weights.is_some()) {
+        return Err(StitchError::InvalidInput("the checkpoint's programs were shuffled, truncated or had invalid programs skipped, so tasks and weights from the input can't be matched up with them".to_string()))
+    }
+    if let Some(tasks) = &tasks {
+        if tasks.len() != checkpoint.original.len() {
+            return Err(StitchError::InvalidInput(format!("got {} tasks but the checkpoint has {} programs", tasks.len(), checkpoint.original.len())))
+        }
+    }
+
+    if cfg.silent {
+        cfg.step.quiet = true
+    }
+    
+    if cfg.no_opt {
+        cfg.step.no_opt();
+    }
+
+    // the checkpoint's originals were already shuffled/truncated by the run that wrote them
+    let train_programs: Vec<ExprOwned> = checkpoint.original.iter().enumerate().map(|(i,p)|{
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(p).map_err(|e| StitchError::parse(Some(i), p, e))?;
+        Ok(ExprOwned::new(set,idx))
+    }).collect::<Result<_,StitchError>>()?;
+
+    // previously learned abstractions come after any that were already in the input
+    let mut name_mapping = name_mapping.unwrap_or_default();
+    name_mapping.extend(checkpoint.name_mapping());
+
+    let cost_fn = cfg.step.cost.expr_cost();
+
+    if !cfg.silent {
+        info!(target: "stitch::multistep", "{}","**********".blue().bold());
+        info!(target: "stitch::multistep", "{}","* Stitch *".blue().bold());
+        info!(target: "stitch::multistep", "{}","**********".blue().bold());
+        programs_info(&train_programs, &cost_fn);
+        info!(target: "stitch::multistep", "Resuming after {} abstractions", checkpoint.abstractions.len());
+    }
+
+    let step_results = multistep_compression_internal(
+        &train_programs,
+        tasks.clone(),
+        weights.clone(),
+        Some(name_mapping.clone()),
+        inventions.as_deref(),
+        None,
+        Some(checkpoint),
+        observer,
+        &cfg,
+    )?;
+
+    let json_res = json_of_run(&step_results, Some(checkpoint), &train_programs, &weights, &tasks, &name_mapping, inventions.as_deref(), &cost_fn, &cfg, !cfg.no_json_programs);
+
+    Ok((step_results, json_res))
+}
+
+/// `inventions` are the ones that were already in the input and `name_mapping` is their [name, #(lambda ...)] pairs, which
+/// `rewritten_dreamcoder` needs if no new abstractions were found
+#[allow(clippy::too_many_arguments)]
+pub fn json_of_step_results(step_results: &[CompressionStepResult], train_programs: &[ExprOwned], weights: Option<Vec<f32>>, tasks: Option<Vec<String>>, name_mapping: &[(String,String)], inventions: Option<&[Invention]>, cost_fn: &ExprCost, cfg: &MultistepCompressionConfig) -> serde_json::Value {
+    json_of_run(step_results, None, train_programs, &weights, &tasks, name_mapping, inventions, cost_fn, cfg, !cfg.no_json_programs)
+}
+
+/// A CSV with one row per abstraction in `step_results`, numbering iterations from `first_iteration` (the number of
+/// resumed abstractions, if any, since iterations are numbered from 0 everywhere else)
+pub fn csv_of_step_results(step_results: &[CompressionStepResult], first_iteration: usize) -> String {
+    let mut header: Vec<&str> = vec!["iteration", "name", "arity", "utility", "final_cost", "compression_ratio", "cumulative_compression_ratio",
+        "num_uses", "num_tasks", "prep_millis", "search_millis", "worklist_steps"];
+    header.extend(Stats::default().counters().iter().map(|(name, _)| *name));
+    header.extend(["zippers_millis", "analyses_millis", "arity_zero_millis", "rewrite_millis"]);
+    let mut csv = header.join(",");
+    csv.push('\n');
+    for (i, res) in step_results.iter().enumerate() {
+        let mut row: Vec<String> = vec![
+            (first_iteration + i).to_string(),
+            csv_field(&res.inv.name),
+            res.inv.arity.to_string(),
+            res.done.utility.to_string(),
+            res.final_cost.to_string(),
+            res.multiplier.to_string(),
+            res.multiplier_wrt_orig.to_string(),
+            res.uses.to_string(),
+            res.num_tasks.to_string(),
+            res.timings.prep_millis.to_string(),
+            res.timings.search_millis.to_string(),
+            res.stats.worklist_steps.to_string(),
+        ];
+        row.extend(res.stats.counters().iter().map(|(_, count)| count.to_string()));
+        row.extend([res.timings.zippers_millis, res.timings.analyses_millis, res.timings.arity_zero_millis, res.timings.rewrite_millis].iter().map(|millis| millis.to_string()));
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// quote a CSV field if it needs it
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// json of a whole run, where `resume` holds the abstractions learned before `step_results` if this run was resumed. The
+/// program lists are only built if `include_programs` is set. `name_mapping` has the [name, #(lambda ...)] pair of every
+/// abstraction the programs could use before `step_results`, i.e. those from the input and any resumed ones. `inventions`
+/// are the ones from the input, which are listed as `prior_abstractions`.
+#[allow(clippy::too_many_arguments)]
+fn json_of_run(step_results: &[CompressionStepResult], resume: Option<&Checkpoint>, train_programs: &[ExprOwned], weights: &Option<Vec<f32>>, tasks: &Option<Vec<String>>, name_mapping: &[(String,String)], inventions: Option<&[Invention]>, cost_fn: &ExprCost, cfg: &MultistepCompressionConfig, include_programs: bool) -> serde_json::Value {
+    let rewritten: &[ExprOwned] = step_results.iter().last().map(|res| &res.rewritten[..])
+        .or_else(|| resume.map(|checkpoint| &checkpoint.rewritten[..]))
+        .unwrap_or(train_programs);
+    let original_cost = min_cost(train_programs, weights, tasks, cost_fn);
+    let final_cost = min_cost(rewritten, weights, tasks, cost_fn);
+    let mut abstractions: Vec<serde_json::Value> = resume.map(|checkpoint| checkpoint.abstractions.clone()).unwrap_or_default();
+    abstractions.extend(step_results.iter().map(|inv| inv.json(&cfg.step)));
+    let mut json_res = json!({
         "cmd": std::env::args().join(" "),
         "args": cfg,
         "original_cost": original_cost,
         "final_cost": final_cost,
         "compression_ratio": compression_factor(original_cost,final_cost),
-        "num_abstractions": step_results.len(),
-        "original": train_programs.iter().map(|p| p.to_string()).collect::<Vec<String>>(),
-        "rewritten": rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>(),
-        "rewritten_dreamcoder": rewritten_dreamcoder,
-        "abstractions": step_results.iter().map(|inv| inv.json(&cfg.step)).collect::<Vec<serde_json::Value>>(),
-    })
+        "num_abstractions": abstractions.len(),
+    });
+    // inserted one by one to keep the usual key order
+    let json_obj = json_res.as_object_mut().unwrap();
+    if include_programs {
+        let rewritten_dreamcoder = if !cfg.step.rewritten_dreamcoder { None } else {
+            let rewritten_dreamcoder = step_results.iter().last().map(|res| res.rewritten_dreamcoder.clone().unwrap())
+                .or_else(|| resume.and_then(|checkpoint| checkpoint.rewritten_dreamcoder.clone()))
+                .unwrap_or_else(||rewritten.iter().map(|p| dreamcoder_string(p.immut(), name_mapping)).collect::<Vec<String>>());
+            Some(rewritten_dreamcoder)
+        };
+        json_obj.insert("original".into(), json!(train_programs.iter().map(|p| p.to_string()).collect::<Vec<String>>()));
+        json_obj.insert("rewritten".into(), json!(rewritten.iter().map(|p| p.to_string()).collect::<Vec<String>>()));
+        json_obj.insert("rewritten_dreamcoder".into(), json!(rewritten_dreamcoder));
+    }
+    if let Some(inventions) = inventions {
+        let dc_of_name: FxHashMap<&str, &str> = name_mapping.iter().map(|(name, dc)| (name.as_str(), dc.as_str())).collect();
+        json_obj.insert("prior_abstractions".into(), json!(inventions.iter().map(|inv| json!({
+            "body": inv.body.to_string(),
+            "dreamcoder": dc_of_name.get(inv.name.as_str()),
+            "arity": inv.arity,
+            "name": inv.name,
+        })).collect::<Vec<_>>()));
+    }
+    json_obj.insert("abstractions".into(), json!(abstractions));
+    json_res
 }
@@ -0,0 +1,275 @@
+use crate::*;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, ValueSource};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+impl MultistepCompressionConfig {
+    /// A builder starting from the same defaults as `compress`, for setting options without going through clap:
+    /// `MultistepCompressionConfig::builder().iterations(10).max_arity(3).build()?`
+    pub fn builder() -> CompressionConfigBuilder {
+        CompressionConfigBuilder { cfg: MultistepCompressionConfig::new() }
+    }
+
+    /// Errors on options that can't be used together, which would otherwise panic partway through compression
+    pub fn validate(&self) -> Result<(), StitchError> {
+        let step = &self.step;
+        let invalid = |message: &str| Err(StitchError::InvalidInput(message.to_string()));
+        if step.eta_long && !step.utility_by_rewrite && !step.no_mismatch_check {
+            return invalid("eta_long requires utility_by_rewrite (or no_mismatch_check)")
+        }
+        if step.follow_prune && step.follow.is_none() {
+            return invalid("follow_prune requires an abstraction to follow")
+        }
+        if step.threads == 0 {
+            return invalid("threads must be at least 1")
+        }
+        if step.batch == 0 {
+            return invalid("batch must be at least 1")
+        }
+        if step.structure_penalty <= 0.0 {
+            return invalid("structure_penalty must be positive")
+        }
+        if self.preset == Some(Preset::Neurosymbolic) && step.fused_lambda_tags.tags().is_none() {
+            return invalid("the neurosymbolic preset needs fused_lambda_tags")
+        }
+        Ok(())
+    }
+
+    /// Read a config from a .toml or .json file laid out like the `args` of an output json, with the options of
+    /// `CompressionStepConfig` under `step` and the costs under `step.cost`. Options that are left out keep their
+    /// defaults, so the `args` of a previous run can be reused as is
+    pub fn from_file(path: &Path) -> Result<Self, StitchError> {
+        serde_json::from_value(read_config(path)?).map_err(|e| StitchError::format(path, e))
+    }
+
+    /// Parse `compress`-style flags like `parse_from`, then `resolve` them
+    pub fn from_args<I, T>(args: I) -> Result<Self, StitchError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().try_get_matches_from(args).map_err(|e| StitchError::InvalidInput(e.to_string()))?;
+        let cli = Self::from_arg_matches(&matches).map_err(|e| StitchError::InvalidInput(e.to_string()))?;
+        cli.resolve(&matches)
+    }
+
+    /// Apply `--preset` and `--config`: options start from the preset (or the defaults), are overridden by the file,
+    /// and then by anything given explicitly on the command line (according to `matches`, which this config was
+    /// parsed from). The file can also pick the preset
+    pub fn resolve(&self, matches: &ArgMatches) -> Result<Self, StitchError> {
+        if self.preset.is_none() && self.config.is_none() {
+            return Ok(self.clone())
+        }
+        let invalid = |e: serde_json::Error| match &self.config {
+            Some(path) => StitchError::format(path, e),
+            None => StitchError::InvalidInput(e.to_string()),
+        };
+        // just the options that were set by the file or on the command line
+        let mut explicit = match &self.config {
+            Some(path) => read_config(path)?,
+            None => json!({}),
+        };
+        override_explicit(&mut explicit, &json!(self), matches);
+        let preset: Option<Preset> = serde_json::from_value(explicit["preset"].clone()).map_err(invalid)?;
+
+        let mut cfg = json!(preset.as_ref().map_or_else(MultistepCompressionConfig::new, Preset::config));
+        overlay(&mut cfg, explicit);
+        let cfg: Self = serde_json::from_value(cfg).map_err(invalid)?;
+        Ok(MultistepCompressionConfig { config: self.config.clone(), cancel: self.cancel.clone(), ..cfg })
+    }
+}
+
+/// Named bundles of options for common kinds of corpora, see `--preset`. Options given on the command line or in a
+/// `--config` file take precedence over the preset's.
+#[derive(Debug, Clone, PartialEq, Eq, clap::ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Matches the abstractions and outputs of DreamCoder's compressor: `eta_long` with `utility_by_rewrite` (since
+    /// abstractions have to be put in beta-normal eta-long form), DreamCoder's costs, and `rewritten_dreamcoder`
+    Dreamcoder,
+    /// For corpora with tagged lambdas like `lam_2`, e.g. from neurosymbolic DSLs: `no_curried_bodies` so abstractions
+    /// are always fully applied. Needs `fused_lambda_tags` too, listing the tags of lambdas that have to stay attached
+    /// to their bodies
+    Neurosymbolic,
+    /// A quick first look at a new corpus: arity one abstractions only, without search stats
+    Fast,
+}
+
+impl Preset {
+    /// The defaults with this preset's options set
+    pub fn config(&self) -> MultistepCompressionConfig {
+        let mut cfg = MultistepCompressionConfig::new();
+        cfg.preset = Some(self.clone());
+        let step = &mut cfg.step;
+        match self {
+            Preset::Dreamcoder => {
+                step.eta_long = true;
+                step.utility_by_rewrite = true;
+                step.rewritten_dreamcoder = true;
+                let cost = ExprCost::dreamcoder();
+                step.cost = CostConfig {
+                    cost_lam: cost.cost_lam as usize,
+                    cost_app: cost.cost_app as usize,
+                    cost_var: cost.cost_var as usize,
+                    cost_ivar: cost.cost_ivar as usize,
+                    cost_prim_default: cost.cost_prim_default as usize,
+                };
+            }
+            Preset::Neurosymbolic => {
+                step.no_curried_bodies = true;
+            }
+            Preset::Fast => {
+                step.max_arity = 1;
+                step.no_stats = true;
+            }
+        }
+        cfg
+    }
+
+    /// A builder starting from this preset's options
+    pub fn builder(&self) -> CompressionConfigBuilder {
+        CompressionConfigBuilder { cfg: self.config() }
+    }
+}
+
+/// a config file as json, whichever format it was written in
+fn read_config(path: &Path) -> Result<Value, StitchError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| StitchError::io(path, e))?;
+    let cfg: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| StitchError::format(path, e))?,
+        Some("json") => serde_json::from_str(&contents).map_err(|e| StitchError::format(path, e))?,
+        _ => return Err(StitchError::format(path, "config files should be .toml or .json")),
+    };
+    if !cfg.is_object() {
+        return Err(StitchError::format(path, "expected a table of options"))
+    }
+    Ok(cfg)
+}
+
+/// recursively replace the options in `cfg` with those in `top`
+fn overlay(cfg: &mut Value, top: Value) {
+    match top {
+        Value::Object(top) if cfg.is_object() => {
+            for (key, value) in top {
+                overlay(&mut cfg[key.as_str()], value);
+            }
+        }
+        top => *cfg = top,
+    }
+}
+
+/// overwrite the options in `cfg` that were passed on the command line with their values from `cli`. Objects are the
+/// flattened configs like `step`, and every other key is a field whose arg id is its name in kebab case
+fn override_explicit(cfg: &mut Value, cli: &Value, matches: &ArgMatches) {
+    for (key, value) in cli.as_object().unwrap() {
+        if value.is_object() {
+            if cfg.get(key).is_none() {
+                cfg[key] = json!({});
+            }
+            override_explicit(&mut cfg[key], value, matches);
+        } else if matches.value_source(key.replace('_', "-").as_str()) == Some(ValueSource::CommandLine) {
+            cfg[key] = value.clone();
+        }
+    }
+}
+
+/// Builds a `MultistepCompressionConfig` with one method per option, checking it with `validate` at the end.
+/// See the fields of `MultistepCompressionConfig`, `CompressionStepConfig` and `CostConfig` for what each option does.
+#[derive(Debug, Clone)]
+pub struct CompressionConfigBuilder {
+    cfg: MultistepCompressionConfig,
+}
+
+macro_rules! setters {
+    ($config:ident, $($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets `", stringify!($field), "`")]
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.$config().$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl CompressionConfigBuilder {
+    fn multistep(&mut self) -> &mut MultistepCompressionConfig {
+        &mut self.cfg
+    }
+
+    fn step(&mut self) -> &mut CompressionStepConfig {
+        &mut self.cfg.step
+    }
+
+    fn cost(&mut self) -> &mut CostConfig {
+        &mut self.cfg.step.cost
+    }
+
+    setters!(multistep,
+        iterations: usize,
+        abstraction_prefix: String,
+        previous_abstractions: usize,
+        shuffle: bool,
+        truncate: Option<usize>,
+        no_opt: bool,
+        silent: bool,
+        verbose_rewrite: bool,
+        checkpoint: Option<PathBuf>,
+        no_json_programs: bool,
+        skip_invalid: bool,
+        preset: Option<Preset>,
+        cancel: Cancellation,
+    );
+
+    setters!(step,
+        max_arity: usize,
+        threads: usize,
+        no_stats: bool,
+        batch: usize,
+        dynamic_batch: bool,
+        eta_long: bool,
+        no_curried_metavars: bool,
+        no_curried_bodies: bool,
+        inv_candidates: usize,
+        hole_choice: HoleChoice,
+        no_mismatch_check: bool,
+        follow: Option<String>,
+        follow_prune: bool,
+        verbose_worklist: bool,
+        verbose_best: bool,
+        print_stats: usize,
+        show_rewritten: bool,
+        rewritten_dreamcoder: bool,
+        rewritten_intermediates: bool,
+        inv_arg_cap: bool,
+        allow_single_task: bool,
+        no_opt_single_use: bool,
+        no_opt_upper_bound: bool,
+        no_opt_force_multiuse: bool,
+        no_opt_useless_abstract: bool,
+        no_opt_arity_zero: bool,
+        no_other_util: bool,
+        structure_penalty: f32,
+        rewrite_check: bool,
+        utility_by_rewrite: bool,
+        dreamcoder_comparison: bool,
+        quiet: bool,
+        fused_lambda_tags: FusedLambdaTags,
+    );
+
+    setters!(cost,
+        cost_lam: usize,
+        cost_app: usize,
+        cost_var: usize,
+        cost_ivar: usize,
+        cost_prim_default: usize,
+    );
+
+    /// The finished config, or an error if it has options that can't be used together
+    pub fn build(self) -> Result<MultistepCompressionConfig, StitchError> {
+        self.cfg.validate()?;
+        Ok(self.cfg)
+    }
+}
@@ -0,0 +1,169 @@
+use lambdas::*;
+use serde::Serialize;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fmt::{self, Display};
+
+/// Summary statistics of a corpus, as printed by the `stats` binary
+#[derive(Debug, Clone, Serialize)]
+pub struct CorpusStats {
+    pub num_programs: usize,
+    pub num_tasks: usize,
+    pub cost: Summary, // under the cost function passed in
+    pub length: Summary, // number of terminals
+    pub depth: Summary,
+    pub num_nodes: usize, // total size of the programs
+    pub distinct_subtrees: usize, // number of nodes after structural hashing
+    pub prim_frequencies: Vec<(String, usize)>, // most frequent first
+    pub most_repeated: Vec<RepeatedSubtree>, // most frequent first, excluding primitives and variables
+    pub lambda_depths: Vec<(usize, usize)>, // (deepest lambda nesting, number of programs), by depth
+    pub programs_per_task: Vec<(usize, usize)>, // (number of programs, number of tasks with that many), by number of programs
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub std: f64,
+    pub min: i32,
+    pub max: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatedSubtree {
+    pub expr: String,
+    pub occurrences: usize, // counting repeats within a program
+    pub num_programs: usize, // number of programs it occurs in
+    pub cost: i32,
+}
+
+impl Summary {
+    /// all zeros for an empty corpus
+    fn new(data: &[i32]) -> Self {
+        if data.is_empty() {
+            return Summary { mean: 0., std: 0., min: 0, max: 0 }
+        }
+        let mean = data.iter().sum::<i32>() as f64 / data.len() as f64;
+        let variance = data.iter().map(|x|f64::powi(*x as f64 - mean, 2)).sum::<f64>() / data.len() as f64;
+        Summary { mean, std: f64::sqrt(variance), min: data.iter().copied().min().unwrap(), max: data.iter().copied().max().unwrap() }
+    }
+}
+
+impl CorpusStats {
+    /// `tasks` are the task of each program, if not given every program is its own task. `top` is how many of
+    /// the most repeated subtrees to keep.
+    pub fn new(programs: &[ExprOwned], tasks: Option<&[String]>, cost_fn: &ExprCost, top: usize) -> Self {
+        let length_fn = ExprCost::num_terminals();
+        let costs: Vec<i32> = programs.iter().map(|p| p.cost(cost_fn)).collect();
+        let lengths: Vec<i32> = programs.iter().map(|p| p.cost(&length_fn)).collect();
+        let depths: Vec<i32> = programs.iter().map(|p| p.depth() as i32).collect();
+
+        // structurally hashed so repeated subtrees share an idx
+        let mut set = ExprSet::empty(Order::ChildFirst, false, true);
+        let roots: Vec<Idx> = programs.iter().map(|p| p.immut().copy_rec(&mut set)).collect();
+
+        let mut occurrences: Vec<usize> = vec![0; set.len()];
+        let mut programs_of_node: Vec<usize> = vec![0; set.len()];
+        let mut prim_counts: FxHashMap<String, usize> = FxHashMap::default();
+        let mut lambda_depth_counts: FxHashMap<usize, usize> = FxHashMap::default();
+        for root in roots.iter() {
+            let mut seen: FxHashSet<Idx> = FxHashSet::default();
+            let mut max_lambda_depth = 0;
+            // (node, number of lambdas above it)
+            let mut stack: Vec<(Idx, usize)> = vec![(*root, 0)];
+            while let Some((node, lambda_depth)) = stack.pop() {
+                occurrences[node] += 1;
+                if seen.insert(node) {
+                    programs_of_node[node] += 1;
+                }
+                match &set[node] {
+                    Node::Prim(p) => *prim_counts.entry(p.to_string()).or_default() += 1,
+                    Node::App(f, x) => {
+                        stack.push((*f, lambda_depth));
+                        stack.push((*x, lambda_depth));
+                    }
+                    Node::Lam(b, _) => {
+                        max_lambda_depth = max_lambda_depth.max(lambda_depth + 1);
+                        stack.push((*b, lambda_depth + 1));
+                    }
+                    Node::Var(_, _) | Node::IVar(_) => {}
+                }
+            }
+            *lambda_depth_counts.entry(max_lambda_depth).or_default() += 1;
+        }
+
+        let mut prim_frequencies: Vec<(String, usize)> = prim_counts.into_iter().collect();
+        prim_frequencies.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut repeated: Vec<Idx> = set.iter().filter(|idx| matches!(set[*idx], Node::App(_, _) | Node::Lam(_, _)) && occurrences[*idx] > 1).collect();
+        repeated.sort_by(|a, b| occurrences[*b].cmp(&occurrences[*a]).then_with(|| a.cmp(b)));
+        let most_repeated: Vec<RepeatedSubtree> = repeated.into_iter().take(top).map(|idx| RepeatedSubtree {
+            expr: set.get(idx).to_string(),
+            occurrences: occurrences[idx],
+            num_programs: programs_of_node[idx],
+            cost: set.get(idx).cost(cost_fn),
+        }).collect();
+
+        let mut lambda_depths: Vec<(usize, usize)> = lambda_depth_counts.into_iter().collect();
+        lambda_depths.sort();
+
+        // programs without a task are each their own task, like in compression
+        let mut programs_of_task: FxHashMap<&str, usize> = FxHashMap::default();
+        if let Some(tasks) = tasks {
+            for task in tasks.iter() {
+                *programs_of_task.entry(task.as_str()).or_default() += 1;
+            }
+        }
+        let num_tasks = if tasks.is_some() { programs_of_task.len() } else { programs.len() };
+        let mut task_size_counts: FxHashMap<usize, usize> = FxHashMap::default();
+        if tasks.is_some() {
+            for count in programs_of_task.values() {
+                *task_size_counts.entry(*count).or_default() += 1;
+            }
+        } else if !programs.is_empty() {
+            task_size_counts.insert(1, programs.len());
+        }
+        let mut programs_per_task: Vec<(usize, usize)> = task_size_counts.into_iter().collect();
+        programs_per_task.sort();
+
+        CorpusStats {
+            num_programs: programs.len(),
+            num_tasks,
+            cost: Summary::new(&costs),
+            length: Summary::new(&lengths),
+            depth: Summary::new(&depths),
+            num_nodes: occurrences.iter().sum(),
+            distinct_subtrees: set.len(),
+            prim_frequencies,
+            most_repeated,
+            lambda_depths,
+            programs_per_task,
+        }
+    }
+}
+
+impl Display for CorpusStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Number of programs: {}", self.num_programs)?;
+        writeln!(f, "Number of tasks: {}", self.num_tasks)?;
+        for (name, s) in [("Costs", &self.cost), ("Lengths", &self.length), ("Depths", &self.depth)] {
+            writeln!(f, "{name}: mean={:.2}, std={:.2}, min={}, max={}", s.mean, s.std, s.min, s.max)?;
+        }
+        writeln!(f, "Nodes: {} total, {} distinct subtrees", self.num_nodes, self.distinct_subtrees)?;
+        writeln!(f, "\nPrimitive frequencies:")?;
+        for (prim, count) in self.prim_frequencies.iter() {
+            writeln!(f, "  {count:>8}  {prim}")?;
+        }
+        writeln!(f, "\nMost repeated subtrees (occurrences, programs, cost):")?;
+        for s in self.most_repeated.iter() {
+            writeln!(f, "  {:>8} {:>8} {:>8}  {}", s.occurrences, s.num_programs, s.cost, s.expr)?;
+        }
+        writeln!(f, "\nDeepest lambda nesting (depth: programs):")?;
+        for (depth, count) in self.lambda_depths.iter() {
+            writeln!(f, "  {depth:>3}: {count}")?;
+        }
+        writeln!(f, "\nPrograms per task (programs: tasks):")?;
+        for (size, count) in self.programs_per_task.iter() {
+            writeln!(f, "  {size:>3}: {count}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Translation between stitch programs and DreamCoder syntax, where inventions are written out in full
+//! inline as `#(lambda ...)` rather than by name. Both directions work on the expression tree: inventions
+//! are substituted for `Prim` nodes when printing, and `#(...)` spans are parsed back into `Invention`s.
+
+use crate::*;
+use lambdas::*;
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// Replace the ivars in an expr with vars
+pub fn ivar_to_dc(e: &mut ExprMut, depth: i32, arity: i32) {
+    match e.node().clone() {
+        // dreamcoder doesn't care about the tags, so just use -1 for all of them
+        Node::IVar(i) => *e.node() = Node::Var(depth + (arity - 1 - i), -1), // the higher the ivar the smaller the var
+        Node::Var(_, _) => {},
+        Node::Prim(_) => {},
+        Node::App(f,x) => {
+            ivar_to_dc(&mut e.get(f), depth, arity);
+            ivar_to_dc(&mut e.get(x), depth, arity);
+        },
+        Node::Lam(b, _) => {
+            ivar_to_dc(&mut e.get(b), depth+1, arity);
+        },
+    }
+}
+
+/// The dreamcoder form of an invention: its body wrapped in a lambda per argument and marked with a `#`. Any
+/// earlier inventions it uses are inlined using `dreamcoder_translations`, a list of [name, #(lambda ...)] pairs.
+pub fn dc_inv_str(inv: &Invention, dreamcoder_translations: &[(String, String)]) -> String {
+    let mut body = inv.body.clone();
+    assert!(body.set.struct_hash.is_none()); // we dont attempt to maintain the struct hash
+    ivar_to_dc(&mut body.as_mut(), 0, inv.arity as i32);
+
+    // wrap in lambdas for dremacoder
+    for _ in 0..inv.arity {
+        // dreamcoder doesn't care about the tags, so just use -1 for all of them
+        body.idx = body.set.add(Node::Lam(body.idx, -1));
+    }
+    format!("#{}", dreamcoder_string(body.immut(), dreamcoder_translations))
+}
+
+/// Print an expression in dreamcoder syntax, with `lambda` instead of `lam` and every primitive that names an invention
+/// in `dreamcoder_translations` (a list of [name, #(lambda ...)] pairs) replaced by its #(lambda ...) form.
+pub fn dreamcoder_string(e: Expr, dreamcoder_translations: &[(String, String)]) -> String {
+    let translations: FxHashMap<&str, &str> = dreamcoder_translations.iter().map(|(name, dc)| (name.as_str(), dc.as_str())).collect();
+    let mut res = String::new();
+    write_dreamcoder(e, &translations, &mut res);
+    res
+}
+
+fn write_dreamcoder(e: Expr, translations: &FxHashMap<&str, &str>, out: &mut String) {
+    match e.node() {
+        Node::Prim(p) => {
+            let p = p.to_string();
+            out.push_str(translations.get(p.as_str()).copied().unwrap_or(&p));
+        }
+        Node::Var(i, tag) => if *tag == -1 { write!(out, "${i}").unwrap() } else { write!(out, "${i}_{tag}").unwrap() },
+        Node::IVar(i) => write!(out, "#{i}").unwrap(),
+        Node::Lam(b, tag) => {
+            if *tag == -1 {
+                out.push_str("(lambda ");
+            } else {
+                write!(out, "(lambda_{tag} ").unwrap();
+            }
+            write_dreamcoder(e.get(*b), translations, out);
+            out.push(')');
+        }
+        Node::App(_,_) => {
+            // curried applications are printed flat like (f x y)
+            let mut args = vec![];
+            let mut head = e;
+            while let Node::App(f,x) = head.node() {
+                args.push(*x);
+                head = head.get(*f);
+            }
+            out.push('(');
+            write_dreamcoder(head, translations, out);
+            for x in args.into_iter().rev() {
+                out.push(' ');
+                write_dreamcoder(e.get(x), translations, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// The inventions of a DreamCoder DSL, parsed from their #(lambda ...) form into real abstractions. Each one is named
+/// `dreamcoder_abstraction_{i}` and any inventions used inside its body refer to the earlier ones by name.
+#[derive(Debug, Clone, Default)]
+pub struct DreamcoderInventions {
+    pub name_mapping: Vec<(String, String)>, // [name, #(lambda ...)] for each invention
+    pub inventions: Vec<Invention>,
+    name_of_dc: FxHashMap<String, String>,
+}
+
+impl DreamcoderInventions {
+    /// parse the #(lambda ...) productions of a DreamCoder DSL. These should be in increasing length so that the inventions
+    /// used inside another invention come before it, which is also the order they get numbered in.
+    pub fn from_productions(dc_invs: &[String]) -> Result<Self, String> {
+        let mut res = DreamcoderInventions::default();
+        for (i, dc_inv) in dc_invs.iter().enumerate() {
+            let name = format!("dreamcoder_abstraction_{i}");
+            let inv = res.parse_invention(dc_inv, &name)?;
+            res.name_of_dc.insert(dc_inv.clone(), name.clone());
+            res.name_mapping.push((name, dc_inv.clone()));
+            res.inventions.push(inv);
+        }
+        Ok(res)
+    }
+
+    /// A program in dreamcoder syntax with each inlined #(lambda ...) invention replaced by its name, so stitch can parse it.
+    pub fn replace_inventions(&self, program: &str) -> Result<String, String> {
+        let mut res = String::with_capacity(program.len());
+        let mut rest = program;
+        while let Some(start) = rest.find("#(") {
+            res.push_str(&rest[..start]);
+            let len = matching_paren(&rest[start+1..]).ok_or_else(|| format!("unbalanced parentheses in dreamcoder invention in {program:?}"))?;
+            let dc_inv = &rest[start..start+1+len];
+            let name = self.name_of_dc.get(dc_inv).ok_or_else(|| format!("dreamcoder invention {dc_inv} is not in the DSL"))?;
+            res.push_str(name);
+            rest = &rest[start+1+len..];
+        }
+        res.push_str(rest);
+        Ok(res)
+    }
+
+    /// Parse `#(lambda (lambda ... body))` into an invention with one argument per leading lambda, where
+    /// the variables bound by those lambdas become ivars.
+    fn parse_invention(&self, dc_inv: &str, name: &str) -> Result<Invention, String> {
+        let inner = dc_inv.strip_prefix('#').ok_or_else(|| format!("dreamcoder invention {dc_inv} doesn't start with #"))?;
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(&self.replace_inventions(inner)?)
+            .map_err(|e| format!("failed to parse dreamcoder invention {dc_inv}: {e}"))?;
+        let mut arity = 0;
+        let mut body = idx;
+        while let Node::Lam(b, _) = set[body] {
+            body = b;
+            arity += 1;
+        }
+        let mut body_set = ExprSet::empty(Order::ChildFirst, false, false);
+        let body_idx = dc_to_ivar(set.get(body), 0, arity, &mut body_set)
+            .map_err(|e| format!("in dreamcoder invention {dc_inv}: {e}"))?;
+        Ok(Invention::new(ExprOwned::new(body_set, body_idx), arity as usize, name))
+    }
+}
+
+/// the inverse of `ivar_to_dc`: copies `e` into `set`, turning the vars that point above `depth` into the ivars of an arity `arity` invention
+fn dc_to_ivar(e: Expr, depth: i32, arity: i32, set: &mut ExprSet) -> Result<Idx, String> {
+    let node = match e.node() {
+        Node::Var(i, _) if *i >= depth => {
+            if *i - depth >= arity {
+                return Err(format!("free variable ${i}"))
+            }
+            Node::IVar(arity - 1 - (*i - depth))
+        }
+        Node::Var(i, tag) => Node::Var(*i, *tag),
+        Node::Prim(p) => Node::Prim(p.clone()),
+        Node::IVar(i) => return Err(format!("unexpected #{i}")),
+        Node::Lam(b, tag) => Node::Lam(dc_to_ivar(e.get(*b), depth + 1, arity, set)?, *tag),
+        Node::App(f,x) => {
+            let f = dc_to_ivar(e.get(*f), depth, arity, set)?;
+            let x = dc_to_ivar(e.get(*x), depth, arity, set)?;
+            Node::App(f, x)
+        }
+    };
+    Ok(set.add(node))
+}
+
+/// length of the parenthesized expression at the start of `s`, including both parens
+fn matching_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1)
+                }
+            }
+            _ => {}
+        }
+        if depth == 0 {
+            return None
+        }
+    }
+    None
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::validation::ProgramError;
+
+/// Errors from loading inputs, compressing, and rewriting. These are problems with what was passed in (or, for
+/// `CostMismatch`, a disagreement between the utility calculation and the actual rewrite) rather than internal
+/// invariants, which are still asserted.
+#[derive(Debug, Clone)]
+pub enum StitchError {
+    /// a file couldn't be opened, read or written
+    Io { path: PathBuf, message: String },
+    /// a file isn't valid json, or doesn't have the fields its format needs
+    Format { path: PathBuf, message: String },
+    /// a program (or abstraction body) couldn't be parsed. `index` is its position in the corpus if it has one
+    Parse { index: Option<usize>, program: String, message: String },
+    /// every program in the corpus that failed to parse
+    InvalidPrograms(Vec<ProgramError>),
+    /// inputs that are well formed on their own but don't fit together, like a different number of tasks and programs
+    InvalidInput(String),
+    /// rewriting with an abstraction gave a different corpus cost than its utility predicted
+    CostMismatch { abstraction: String, expected: i32, actual: i32 },
+    /// compression was stopped through `MultistepCompressionConfig::cancel`
+    Cancelled,
+}
+
+impl StitchError {
+    pub fn io(path: &Path, e: impl fmt::Display) -> Self {
+        StitchError::Io { path: path.to_path_buf(), message: e.to_string() }
+    }
+    pub fn format(path: &Path, e: impl fmt::Display) -> Self {
+        StitchError::Format { path: path.to_path_buf(), message: e.to_string() }
+    }
+    pub fn parse(index: Option<usize>, program: &str, e: impl fmt::Display) -> Self {
+        StitchError::Parse { index, program: program.to_string(), message: e.to_string() }
+    }
+}
+
+impl fmt::Display for StitchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StitchError::Io { path, message } => write!(f, "{}: {message}", path.display()),
+            StitchError::Format { path, message } => write!(f, "{}: {message}", path.display()),
+            StitchError::Parse { index: Some(i), program, message } => write!(f, "failed to parse program {i} {program:?}: {message}"),
+            StitchError::Parse { index: None, program, message } => write!(f, "failed to parse {program:?}: {message}"),
+            StitchError::InvalidPrograms(errors) => {
+                write!(f, "{} invalid program{} (see --skip-invalid to compress the rest anyways):", errors.len(), if errors.len() == 1 { "" } else { "s" })?;
+                errors.iter().try_for_each(|e| write!(f, "\n  {e}"))
+            }
+            StitchError::InvalidInput(message) => write!(f, "{message}"),
+            StitchError::CostMismatch { abstraction, expected, actual } =>
+                write!(f, "cost mismatch when rewriting with {abstraction}: utility predicted a cost of {expected} but rewriting gave {actual}"),
+            StitchError::Cancelled => write!(f, "compression was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for StitchError {}
@@ -1,73 +1,400 @@
-use std::iter::{repeat};
 use std::path::Path;
 use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use clap::ArgEnum;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 use serde_json::de::from_reader;
+use rustc_hash::FxHashSet;
+use crate::trees::*;
+use crate::dreamcoder::*;
+use crate::error::StitchError;
+use crate::compression::{Invention, MultistepCompressionConfig};
+use crate::checkpoint::Checkpoint;
+use crate::validation::ProgramError;
 
 #[derive(Debug, Clone, ArgEnum, Serialize)]
 pub enum InputFormat {
     Dreamcoder,
-    ProgramsList
+    ProgramsList,
+    /// plain text with one program per line. Blank lines and comments are ignored, where a comment starts with a `;`
+    /// at the start of a token (so not in a primitive like `a;b` or a string literal like `"a ; b"`).
+    Sexp,
+    /// a JSON array of objects like {"program": ..., "task": ..., "weight": ..., "id": ..., "meta": {...}} where
+    /// only "program" is required. Other fields are passed through to the rewritten output unchanged.
+    JsonObjects,
+    /// JSON Lines: one program per line, either as a JSON string or as an object like in `json-objects`.
+    Jsonl,
+    /// a JSON array of syntax trees like {"type": ..., "children": [...]} or nested arrays, see [trees.rs] for the encoding.
+    JsonTree,
 }
 
 #[derive(Debug, Clone)]
 pub struct Input {
     pub train_programs: Vec<String>, // Program strings. 
     pub tasks: Option<Vec<String>>, // Task names for each corresponding string.
+    pub weights: Option<Vec<f32>>, // Weight of each corresponding string.
     pub name_mapping: Option<Vec<(String, String)>>, // Vec of [#Dreamcoder invention, fn_i] tuples for any existing inventions in the DSL.
+    pub inventions: Option<Vec<Invention>>, // The existing inventions named in `name_mapping`, as abstractions.
+    pub records: Option<Vec<Value>>, // The original json object of each program, for formats that carry extra fields along with it.
+    pub tree: Option<TreeEncoding>, // How the programs were encoded, if they were loaded from trees.
+    pub lines: Option<Vec<usize>>, // The line of the input file each program is on, for line based formats that skip some lines.
+}
+
+impl Input {
+    /// The input records with each "program" field replaced by the corresponding rewritten program, so
+    /// fields like "id" and "meta" can be joined back up with the rewritten results. None for formats without records.
+    pub fn rewritten_records(&self, rewritten: &[String]) -> Option<Vec<Value>> {
+        self.records.as_ref().map(|records| records.iter().zip(rewritten.iter()).map(|(record, program)| with_program(record, program)).collect())
+    }
+
+    /// Records are matched up with the rewritten programs by position, so they can't be passed through a run that
+    /// shuffles or truncates the programs, or that resumes from a `checkpoint` whose run did (or skipped invalid programs)
+    pub fn check_records_line_up(&self, cfg: &MultistepCompressionConfig, checkpoint: Option<&Checkpoint>) -> Result<(), StitchError> {
+        if self.records.is_none() {
+            return Ok(())
+        }
+        if cfg.shuffle || cfg.truncate.is_some() {
+            return Err(StitchError::InvalidInput("--shuffle and --truncate can't be used with an input that has records, since the records would no longer line up with the rewritten programs".to_string()))
+        }
+        if matches!(checkpoint, Some(checkpoint) if checkpoint.reordered) {
+            return Err(StitchError::InvalidInput("can't resume a run that used --shuffle, --truncate or --skip-invalid with an input that has records, since the records would no longer line up with the rewritten programs".to_string()))
+        }
+        Ok(())
+    }
+
+    /// The input with the programs at `indices` (and their tasks, weights and records) left out, e.g. the ones skipped by `--skip-invalid`
+    pub fn without_programs(&self, indices: &[usize]) -> Input {
+        let skipped: FxHashSet<usize> = indices.iter().copied().collect();
+        let keep = |i: &usize| !skipped.contains(i);
+        let filter = |v: &Vec<String>| v.iter().enumerate().filter(|(i,_)| keep(i)).map(|(_,x)| x.clone()).collect();
+        Input {
+            train_programs: filter(&self.train_programs),
+            tasks: self.tasks.as_ref().map(filter),
+            weights: self.weights.as_ref().map(|weights| weights.iter().enumerate().filter(|(i,_)| keep(i)).map(|(_,w)| *w).collect()),
+            records: self.records.as_ref().map(|records| records.iter().enumerate().filter(|(i,_)| keep(i)).map(|(_,r)| r.clone()).collect()),
+            lines: self.lines.as_ref().map(|lines| lines.iter().enumerate().filter(|(i,_)| keep(i)).map(|(_,l)| *l).collect()),
+            ..self.clone()
+        }
+    }
+
+    /// Fill in the line each of `errors` is on in the input file, if the format has lines
+    pub fn locate(&self, errors: &mut [ProgramError]) {
+        if let Some(lines) = &self.lines {
+            for e in errors.iter_mut() {
+                e.line = lines.get(e.index).copied();
+            }
+        }
+    }
+
+    /// `locate` for the programs of an `InvalidPrograms` error, other errors are left as is
+    pub fn locate_error(&self, mut e: StitchError) -> StitchError {
+        if let StitchError::InvalidPrograms(errors) = &mut e {
+            self.locate(errors);
+        }
+        e
+    }
+
+    /// How to turn programs back into trees: the encoding they were loaded with, or objects without arity tags
+    /// if they didn't come from trees.
+    pub fn tree_encoding(&self) -> TreeEncoding {
+        self.tree.clone().unwrap_or(TreeEncoding { shape: TreeShape::Objects, cfg: TreeConfig::default() })
+    }
+}
+
+/// copy of `record` with its "program" field replaced
+fn with_program(record: &Value, program: &str) -> Value {
+    let mut record = record.clone();
+    record["program"] = Value::String(program.to_string());
+    record
 }
 
 impl InputFormat {
-    pub fn load_programs_and_tasks(&self, path: &Path) -> Result<Input, String> {
+    pub fn load_programs_and_tasks(&self, path: &Path) -> Result<Input, StitchError> {
+        self.load_programs_and_tasks_cfg(path, &TreeConfig::default())
+    }
+
+    /// like `load_programs_and_tasks` but with control over how `json-tree` inputs are encoded
+    pub fn load_programs_and_tasks_cfg(&self, path: &Path, tree_cfg: &TreeConfig) -> Result<Input, StitchError> {
         match *self {
             InputFormat::Dreamcoder => {
                 // read dreamcoder format
-                let json: Value = from_reader(File::open(path).expect("file not found")).expect("json deserializing error");
+                let json: Value = from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?).map_err(|e| StitchError::format(path, format!("json parser error: {e}")))?;
                 // there should be a "frontiers" field at the toplevel
-                let frontiers = json["frontiers"].as_array().unwrap_or_else(||panic!("json parse error, are you sure you wanted format {:?}?", self));
+                let frontiers = json["frontiers"].as_array().ok_or_else(|| StitchError::format(path, format!("no \"frontiers\" field, are you sure you wanted format {self:?}?")))?;
                 // grab any existing inventions from the DSL
-                let mut dc_invs: Vec<String> = json["DSL"]["productions"].as_array().unwrap().iter().map(|prod|prod["expression"].as_str().unwrap().to_string())
+                let productions = json["DSL"]["productions"].as_array().ok_or_else(|| StitchError::format(path, "no \"DSL.productions\" field"))?;
+                let mut dc_invs: Vec<String> = productions.iter()
+                    .map(|prod| prod["expression"].as_str().map(|s| s.to_string()).ok_or_else(|| StitchError::format(path, format!("production without a string \"expression\" field: {prod}"))))
+                    .collect::<Result<Vec<_>,_>>()?
+                    .into_iter()
                     .filter(|s| s.starts_with('#'))
                     .collect();
                 dc_invs.sort_by_key(|s| s.len()); // increasing length so inventions that build on earlier ones come later
-                let inv_dc_strs: Vec<(String, String)> = dc_invs
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, dc_str)| (format!("dreamcoder_abstraction_{i}"), dc_str)) // TODO: determine if we need to replace these in the future.
-                    .collect();
+                let dc_inventions = DreamcoderInventions::from_productions(&dc_invs).map_err(|e| StitchError::format(path, e))?;
                 let mut programs: Vec<String> = Vec::default();
                 let mut tasks: Vec<String> = Vec::default();
+                let untasked = untasked_prefix(frontiers.iter().filter_map(|frontier| frontier["task"].as_str()));
                 for (i,frontier) in frontiers.iter().enumerate() {
-                    let programs_in_frontier: Vec<String> = frontier["programs"].as_array().unwrap().iter().map(|p|p["program"].as_str().unwrap().to_string())
-                        .map(|p| inv_dc_strs.iter().rev().fold(p, |p, s| p.replace(&s.1, &s.0))) // replace #(lambda ...) with fn_2 etc. Start with highest numbered fn to avoid mangling bodies of other fns.
-                        .collect();
-                    assert!(!programs_in_frontier.iter().any(|p| p.contains('#')));
+                    let frontier_programs = frontier["programs"].as_array().ok_or_else(|| StitchError::format(path, format!("frontier {i} has no \"programs\" field")))?;
+                    let programs_in_frontier: Vec<String> = frontier_programs.iter()
+                        .map(|p| {
+                            let program = p["program"].as_str().ok_or_else(|| StitchError::format(path, format!("frontier {i} has a program without a string \"program\" field: {p}")))?;
+                            dc_inventions.replace_inventions(program).map_err(|e| StitchError::format(path, e)) // replace #(lambda ...) with dreamcoder_abstraction_2 etc.
+                        })
+                        .collect::<Result<_,_>>()?;
                     let task: String = match frontier["task"].as_str(){
                         Some(name) => name.to_string(),
-                        None => i.to_string()
+                        None => format!("{untasked}{i}")
                     };
-                    let task_repeated: Vec<String> = repeat(task).take(programs_in_frontier.len()).collect();
+                    let task_repeated: Vec<String> = vec![task; programs_in_frontier.len()];
                     programs.extend(programs_in_frontier);
                     tasks.extend(task_repeated);
                 }
                 let input = Input {
                     train_programs: programs,
                     tasks: Some(tasks),
-                    name_mapping: Some(inv_dc_strs),
+                    weights: None,
+                    name_mapping: Some(dc_inventions.name_mapping),
+                    inventions: Some(dc_inventions.inventions),
+                    records: None,
+                    tree: None,
+                    lines: None,
                 };
                 Ok(input)
             }
             InputFormat::ProgramsList => {
-                let programs: Vec<String> = from_reader(File::open(path).map_err(|e| format!("file not found, error code {e:?}"))?).map_err(|e| format!("json parser error, are you sure you wanted format {self:?}? Error code was {e:?}"))?;
+                let programs: Vec<String> = from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?).map_err(|e| StitchError::format(path, format!("json parser error, are you sure you wanted format {self:?}? {e}")))?;
+                let input = Input {
+                    train_programs: programs,
+                    tasks: None,
+                    weights: None,
+                    name_mapping: None,
+                    inventions: None,
+                    records: None,
+                    tree: None,
+                    lines: None,
+                };
+                Ok(input)
+            }
+            InputFormat::Sexp => {
+                let text = std::fs::read_to_string(path).map_err(|e| StitchError::io(path, e))?;
+                let mut programs: Vec<String> = Vec::default();
+                let mut lines: Vec<usize> = Vec::default();
+                for (i,line) in text.lines().enumerate() {
+                    let program = without_comment(line).trim();
+                    if program.is_empty() {
+                        continue
+                    }
+                    programs.push(program.to_string());
+                    lines.push(i+1);
+                }
                 let input = Input {
                     train_programs: programs,
                     tasks: None,
+                    weights: None,
                     name_mapping: None,
+                    inventions: None,
+                    records: None,
+                    tree: None,
+                    lines: Some(lines),
                 };
                 Ok(input)
             }
+            InputFormat::JsonObjects => {
+                let records: Vec<Value> = from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?).map_err(|e| StitchError::format(path, format!("json parser error, are you sure you wanted format {self:?}? {e}")))?;
+                input_of_records(records).map_err(|e| StitchError::format(path, e))
+            }
+            InputFormat::Jsonl => {
+                // read line by line so the raw text of a huge file is never in memory all at once. The parsed lines are
+                // all kept though, since records get passed through to the output
+                let reader = BufReader::new(File::open(path).map_err(|e| StitchError::io(path, e))?);
+                let mut lines: Vec<Value> = Vec::default();
+                let mut line_numbers: Vec<usize> = Vec::default();
+                for (i,line) in reader.lines().enumerate() {
+                    let line = line.map_err(|e| StitchError::io(path, format!("line {}: {e}", i+1)))?;
+                    if line.trim().is_empty() {
+                        continue
+                    }
+                    lines.push(serde_json::from_str(&line).map_err(|e| StitchError::format(path, format!("line {}: json parser error: {e}", i+1)))?);
+                    line_numbers.push(i+1);
+                }
+                let input = if lines.iter().all(|line| line.is_string()) {
+                    Input {
+                        train_programs: lines.into_iter().map(|line| line.as_str().unwrap().to_string()).collect(),
+                        tasks: None,
+                        weights: None,
+                        name_mapping: None,
+                        inventions: None,
+                        records: None,
+                        tree: None,
+                        lines: None,
+                    }
+                } else {
+                    input_of_records(lines.into_iter().map(|line| if line.is_string() { json!({"program": line}) } else { line }).collect())
+                        .map_err(|e| StitchError::format(path, e))?
+                };
+                Ok(Input { lines: Some(line_numbers), ..input })
+            }
+            InputFormat::JsonTree => {
+                let trees: Vec<Value> = from_reader(File::open(path).map_err(|e| StitchError::io(path, e))?).map_err(|e| StitchError::format(path, format!("json parser error, are you sure you wanted format {self:?}? {e}")))?;
+                let programs: Vec<String> = trees.iter().enumerate()
+                    .map(|(i,tree)| program_of_tree(tree, tree_cfg).map_err(|e| StitchError::format(path, format!("tree {i}: {e}"))))
+                    .collect::<Result<_,_>>()?;
+                let input = Input {
+                    train_programs: programs,
+                    tasks: None,
+                    weights: None,
+                    name_mapping: None,
+                    inventions: None,
+                    records: None,
+                    tree: Some(TreeEncoding { shape: tree_shape(&trees), cfg: tree_cfg.clone() }),
+                    lines: None,
+                };
+                Ok(input)
+            }
+        }
+    }
+
+    /// write programs in a form that this format can read back in with `load_programs_and_tasks`. `input` is what the
+    /// programs were originally loaded from, for formats that need to carry over extra fields.
+    pub fn write_programs(&self, path: &Path, programs: &[String], input: &Input) -> Result<(), StitchError> {
+        let contents = match *self {
+            InputFormat::ProgramsList => serde_json::to_string_pretty(programs).unwrap(),
+            InputFormat::Sexp => programs.iter().map(|p| format!("{p}\n")).collect::<String>(),
+            InputFormat::JsonObjects => {
+                // if the input wasn't in this format there are no other fields to carry over
+                let records = input.rewritten_records(programs)
+                    .unwrap_or_else(|| programs.iter().map(|p| json!({"program": p})).collect());
+                serde_json::to_string_pretty(&records).unwrap()
+            }
+            InputFormat::Jsonl => {
+                // streamed straight to the file instead of building the whole thing as a string
+                let mut writer = BufWriter::new(File::create(path).map_err(|e| StitchError::io(path, e))?);
+                let lines: Box<dyn Iterator<Item=Value>> = match &input.records {
+                    Some(records) => Box::new(records.iter().zip(programs.iter()).map(|(record, program)| with_program(record, program))),
+                    None => Box::new(programs.iter().map(|program| Value::String(program.clone()))),
+                };
+                for line in lines {
+                    serde_json::to_writer(&mut writer, &line).map_err(|e| StitchError::io(path, e))?;
+                    writer.write_all(b"\n").map_err(|e| StitchError::io(path, e))?;
+                }
+                return writer.flush().map_err(|e| StitchError::io(path, e))
+            }
+            InputFormat::JsonTree => {
+                let enc = input.tree_encoding();
+                let trees: Vec<Value> = programs.iter().enumerate()
+                    .map(|(i,p)| tree_of_program(p, &enc).map_err(|e| StitchError::parse(Some(i), p, e)))
+                    .collect::<Result<_,_>>()?;
+                serde_json::to_string_pretty(&trees).unwrap()
+            }
+            InputFormat::Dreamcoder => return Err(StitchError::InvalidInput(format!("writing programs in format {self:?} is not supported"))),
+        };
+        std::fs::write(path, contents).map_err(|e| StitchError::io(path, e))
+    }
+}
+
+/// `line` up to the first `;` that starts a token outside of a `"..."` literal, which is where its comment starts
+fn without_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut token_start = true;
+    for (i,c) in line.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == ';' && token_start {
+            return &line[..i]
+        } else if c == '"' {
+            in_string = true;
+        }
+        token_start = !in_string && (c.is_whitespace() || c == '(' || c == ')');
+    }
+    line
+}
+
+/// Build an Input out of a list of {"program": ..., "task": ..., "weight": ...} records, where only "program" is required.
+fn input_of_records(records: Vec<Value>) -> Result<Input, String> {
+    let mut programs: Vec<String> = Vec::default();
+    let mut tasks: Vec<String> = Vec::default();
+    let mut weights: Vec<f32> = Vec::default();
+    let untasked = untasked_prefix(records.iter().filter_map(|record| record["task"].as_str()));
+    for (i,record) in records.iter().enumerate() {
+        let program = record["program"].as_str().ok_or_else(|| format!("record {i} has no string \"program\" field: {record}"))?;
+        programs.push(program.to_string());
+        // programs without a task are each their own task, like in the other formats
+        tasks.push(match &record["task"] {
+            Value::Null => format!("{untasked}{i}"),
+            Value::String(task) => task.clone(),
+            task => task.to_string(),
+        });
+        weights.push(match &record["weight"] {
+            Value::Null => 1.0,
+            weight => weight.as_f64().ok_or_else(|| format!("record {i} has a non-numeric \"weight\" field: {weight}"))? as f32,
+        });
+    }
+    let input = Input {
+        train_programs: programs,
+        tasks: if records.iter().any(|record| !record["task"].is_null()) { Some(tasks) } else { None },
+        weights: if records.iter().any(|record| !record["weight"].is_null()) { Some(weights) } else { None },
+        name_mapping: None,
+        inventions: None,
+        records: Some(records),
+        tree: None,
+        lines: None,
+    };
+    Ok(input)
+}
+
+/// A prefix for the made up task names of programs without a task, which no real task name starts with, so a
+/// program's own task never gets merged with a real task that happens to be called e.g. "3"
+fn untasked_prefix<'a>(tasks: impl Iterator<Item = &'a str> + Clone) -> String {
+    let mut prefix = "untasked_".to_string();
+    while tasks.clone().any(|task| task.starts_with(&prefix)) {
+        prefix.insert(0, '_');
+    }
+    prefix
+}
+
+/// The frontiers of a DreamCoder-format json with the programs replaced, in order, by `programs`. Everything
+/// else (frontier order, "request", "logLikelihood", ...) is kept as is. `programs` is in the same order
+/// as the `train_programs` that `InputFormat::Dreamcoder` loads from the same json.
+pub fn dreamcoder_frontiers_with_programs(dc_json: &Value, programs: &[String]) -> Result<Vec<Value>, String> {
+    let frontiers = dc_json["frontiers"].as_array().ok_or("dreamcoder json has no \"frontiers\" field")?;
+    let num_programs: usize = frontiers.iter().map(|frontier| frontier["programs"].as_array().map_or(0, |ps| ps.len())).sum();
+    if num_programs != programs.len() {
+        return Err(format!("dreamcoder json has {num_programs} programs in its frontiers but {} were given", programs.len()));
+    }
+    let mut programs = programs.iter();
+    Ok(frontiers.iter().map(|frontier| {
+        let mut frontier = frontier.clone();
+        if let Some(frontier_programs) = frontier["programs"].as_array_mut() {
+            for p in frontier_programs.iter_mut() {
+                *p = with_program(p, programs.next().unwrap());
+            }
         }
+        frontier
+    }).collect())
+}
+
+/// A DreamCoder checkpoint fragment: the `DSL` of a DreamCoder-format json with `inventions` (in `#(lambda ...)`
+/// form) appended as new productions, and its frontiers with the programs replaced by `programs` (see
+/// `dreamcoder_frontiers_with_programs`). New productions start out with a logProbability of 0, like in
+/// a uniform DreamCoder grammar. `logVariable` and any other DSL fields are kept as is.
+pub fn dreamcoder_checkpoint(dc_json: &Value, inventions: &[String], programs: &[String]) -> Result<Value, String> {
+    let mut dsl = dc_json["DSL"].clone();
+    if dsl["logVariable"].is_null() {
+        return Err("dreamcoder json has no \"DSL.logVariable\" field".to_string())
     }
+    let productions = dsl["productions"].as_array_mut().ok_or("dreamcoder json has no \"DSL.productions\" field")?;
+    productions.extend(inventions.iter().map(|inv| json!({"expression": inv, "logProbability": 0.0})));
+    Ok(json!({
+        "DSL": dsl,
+        "frontiers": dreamcoder_frontiers_with_programs(dc_json, programs)?,
+    }))
 }
@@ -4,6 +4,21 @@ pub mod rewriting;
 pub mod egraphs;
 pub mod util;
 pub mod formats;
+pub mod checkpoint;
+pub mod trees;
+pub mod dreamcoder;
+pub mod report;
+pub mod callgraph;
+pub mod error;
+pub mod validation;
+pub mod corpus_stats;
+pub mod library_diff;
+pub mod matching;
+pub mod server;
+pub mod observer;
+pub mod logging;
+pub mod config;
+pub mod bench;
 
 pub use {
     compression::*,
@@ -11,6 +26,21 @@ pub use {
     egraphs::*,
     util::*,
     formats::*,
+    checkpoint::*,
+    trees::*,
+    dreamcoder::*,
+    report::*,
+    callgraph::*,
+    error::*,
+    validation::*,
+    corpus_stats::*,
+    library_diff::*,
+    matching::*,
+    server::*,
+    observer::*,
+    logging::*,
+    config::*,
+    bench::*,
     lambdas::*,
 };
 
@@ -0,0 +1,242 @@
+use crate::*;
+use lambdas::*;
+use serde::Serialize;
+use serde_json::Value;
+use rustc_hash::FxHashMap;
+use std::fmt::{self, Display};
+
+/// How the library and the rewritten corpus changed between two `compress` runs (their output jsons)
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryDiff {
+    pub kept: Vec<KeptAbstraction>,
+    pub added: Vec<DiffAbstraction>,
+    pub removed: Vec<DiffAbstraction>,
+    pub old_compression_ratio: f64,
+    pub new_compression_ratio: f64,
+    /// None if the two runs weren't on the same programs, or were run with --no-json-programs
+    pub programs: Option<ProgramCostDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffAbstraction {
+    pub name: String,
+    pub body: String,
+    pub arity: usize,
+    pub utility: i64,
+    pub num_uses: i64,
+}
+
+/// An abstraction found by both runs, possibly under different names or with its arguments in a different order
+#[derive(Debug, Clone, Serialize)]
+pub struct KeptAbstraction {
+    pub old: DiffAbstraction,
+    pub new: DiffAbstraction,
+    pub utility_delta: i64,
+    pub num_uses_delta: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramCostDiff {
+    pub old_cost: i64, // total cost of the rewritten programs
+    pub new_cost: i64,
+    pub num_improved: usize,
+    pub num_worsened: usize,
+    pub num_unchanged: usize,
+    /// (program index, old rewritten cost, new rewritten cost) for every program whose cost changed, biggest change first
+    pub changed: Vec<(usize, i64, i64)>,
+}
+
+impl LibraryDiff {
+    /// Diff two output jsons. Rewritten program costs are measured with `cost_fn` so runs with different cost
+    /// settings can be compared on the same scale.
+    pub fn new(old: &Value, new: &Value, cost_fn: &ExprCost) -> Result<Self, String> {
+        // keys are shared between the two libraries so matching abstractions get the same key
+        let mut interner: FxHashMap<String, usize> = FxHashMap::default();
+        let old_abstractions = abstractions_with_keys(old, &mut interner)?;
+        let new_abstractions = abstractions_with_keys(new, &mut interner)?;
+
+        let mut unmatched_new: Vec<Option<&(usize, DiffAbstraction)>> = new_abstractions.iter().map(Some).collect();
+        let mut kept = vec![];
+        let mut removed = vec![];
+        for (key, old_abs) in old_abstractions.iter() {
+            match unmatched_new.iter_mut().find(|new_abs| matches!(new_abs, Some((new_key, _)) if new_key == key)).and_then(|new_abs| new_abs.take()) {
+                Some((_, new_abs)) => kept.push(KeptAbstraction {
+                    old: old_abs.clone(),
+                    new: new_abs.clone(),
+                    utility_delta: new_abs.utility - old_abs.utility,
+                    num_uses_delta: new_abs.num_uses - old_abs.num_uses,
+                }),
+                None => removed.push(old_abs.clone()),
+            }
+        }
+        let added: Vec<DiffAbstraction> = unmatched_new.into_iter().flatten().map(|(_, abs)| abs.clone()).collect();
+
+        let ratio = |out: &Value| out["compression_ratio"].as_f64().ok_or("output json has no \"compression_ratio\" field");
+        Ok(LibraryDiff {
+            kept,
+            added,
+            removed,
+            old_compression_ratio: ratio(old)?,
+            new_compression_ratio: ratio(new)?,
+            programs: program_cost_diff(old, new, cost_fn)?,
+        })
+    }
+}
+
+/// the abstractions of an output json each with a key that's the same for abstractions equal up to ivar
+/// renaming, including in the abstractions they call
+fn abstractions_with_keys(out: &Value, interner: &mut FxHashMap<String, usize>) -> Result<Vec<(usize, DiffAbstraction)>, String> {
+    let abstractions = out["abstractions"].as_array().ok_or("output json has no \"abstractions\" field")?;
+    // [name, @key] pairs for the abstractions so far, since bodies refer to earlier abstractions by name
+    let mut key_of_name: Vec<(String, String)> = vec![];
+    let mut arg_order: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+    let mut res = vec![];
+    for a in abstractions.iter() {
+        let name = a["name"].as_str().ok_or_else(|| format!("abstraction without a name: {a}"))?.to_string();
+        let body = a["body"].as_str().ok_or_else(|| format!("abstraction without a body: {a}"))?.to_string();
+        let arity = a["arity"].as_u64().unwrap_or_default() as usize;
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(&body)?;
+        let mut renamed: Vec<i32> = vec![];
+        let idx = canonicalize(&mut set, idx, &arg_order, &mut renamed);
+        let canonical = dreamcoder_string(set.get(idx), &key_of_name);
+        let num_keys = interner.len();
+        let key = *interner.entry(canonical).or_insert(num_keys);
+        key_of_name.push((name.clone(), format!("@{key}")));
+        // ivars that never show up in the body keep their relative order at the end
+        let mut order: Vec<usize> = renamed.iter().map(|i| *i as usize).collect();
+        order.extend((0..arity).filter(|i| !renamed.contains(&(*i as i32))));
+        arg_order.insert(name.clone(), order);
+        res.push((key, DiffAbstraction {
+            name,
+            body,
+            arity,
+            utility: a["utility"].as_i64().unwrap_or_default(),
+            num_uses: a["num_uses"].as_i64().unwrap_or_default(),
+        }));
+    }
+    Ok(res)
+}
+
+/// Copy of `idx` with ivars renumbered in the order they first appear, so bodies that only differ in argument order
+/// are equal. Calls to earlier abstractions have their arguments put in the callee's canonical order first
+/// (`arg_order[name][j]` is the original position of canonical argument j). `renamed[j]` is the original ivar
+/// that became #j.
+fn canonicalize(set: &mut ExprSet, idx: Idx, arg_order: &FxHashMap<String, Vec<usize>>, renamed: &mut Vec<i32>) -> Idx {
+    match set[idx].clone() {
+        Node::IVar(i) => {
+            let j = match renamed.iter().position(|old| *old == i) {
+                Some(j) => j,
+                None => { renamed.push(i); renamed.len() - 1 }
+            };
+            set.add(Node::IVar(j as i32))
+        }
+        Node::App(_, _) => {
+            let mut args = vec![];
+            let mut head = idx;
+            while let Node::App(f, x) = set[head] {
+                args.push(x);
+                head = f;
+            }
+            args.reverse();
+            if let Node::Prim(p) = &set[head] {
+                if let Some(order) = arg_order.get(&p.to_string()) {
+                    if args.len() >= order.len() {
+                        let reordered: Vec<Idx> = order.iter().map(|i| args[*i]).collect();
+                        args.splice(..order.len(), reordered);
+                    }
+                }
+            }
+            let mut res = canonicalize(set, head, arg_order, renamed);
+            for x in args {
+                let x = canonicalize(set, x, arg_order, renamed);
+                res = set.add(Node::App(res, x));
+            }
+            res
+        }
+        Node::Lam(b, tag) => {
+            let b = canonicalize(set, b, arg_order, renamed);
+            set.add(Node::Lam(b, tag))
+        }
+        node @ (Node::Prim(_) | Node::Var(_, _)) => set.add(node),
+    }
+}
+
+fn program_cost_diff(old: &Value, new: &Value, cost_fn: &ExprCost) -> Result<Option<ProgramCostDiff>, String> {
+    if old["original"].is_null() || old["original"] != new["original"] {
+        return Ok(None)
+    }
+    let costs = |out: &Value| -> Result<Option<Vec<i64>>, String> {
+        let rewritten = match out["rewritten"].as_array() {
+            Some(rewritten) => rewritten,
+            None => return Ok(None),
+        };
+        rewritten.iter().map(|p| {
+            // programs are strings, or records with a "program" field for some input formats
+            let p = p.as_str().or_else(|| p["program"].as_str()).ok_or_else(|| format!("non-string rewritten program: {p}"))?;
+            let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+            let idx = set.parse_extend(p)?;
+            Ok(set.get(idx).cost(cost_fn) as i64)
+        }).collect::<Result<Vec<i64>, String>>().map(Some)
+    };
+    let (old_costs, new_costs) = match (costs(old)?, costs(new)?) {
+        (Some(old_costs), Some(new_costs)) if old_costs.len() == new_costs.len() => (old_costs, new_costs),
+        _ => return Ok(None),
+    };
+    let mut changed: Vec<(usize, i64, i64)> = old_costs.iter().zip(new_costs.iter()).enumerate()
+        .filter(|(_, (old_cost, new_cost))| old_cost != new_cost)
+        .map(|(i, (old_cost, new_cost))| (i, *old_cost, *new_cost))
+        .collect();
+    changed.sort_by_key(|(i, old_cost, new_cost)| (std::cmp::Reverse((new_cost - old_cost).abs()), *i));
+    Ok(Some(ProgramCostDiff {
+        old_cost: old_costs.iter().sum(),
+        new_cost: new_costs.iter().sum(),
+        num_improved: changed.iter().filter(|(_, old_cost, new_cost)| new_cost < old_cost).count(),
+        num_worsened: changed.iter().filter(|(_, old_cost, new_cost)| new_cost > old_cost).count(),
+        num_unchanged: old_costs.len() - changed.len(),
+        changed,
+    }))
+}
+
+impl LibraryDiff {
+    /// Text summary, listing at most `top` changed programs
+    pub fn summary(&self, top: usize) -> String {
+        let mut out = String::new();
+        self.write_summary(&mut out, top).unwrap();
+        out
+    }
+
+    fn write_summary(&self, out: &mut impl fmt::Write, top: usize) -> fmt::Result {
+        writeln!(out, "Compression ratio: {:.2}x -> {:.2}x ({:+.2})", self.old_compression_ratio, self.new_compression_ratio, self.new_compression_ratio - self.old_compression_ratio)?;
+        writeln!(out, "\nKept ({}):", self.kept.len())?;
+        for k in self.kept.iter() {
+            let name = if k.old.name == k.new.name { k.new.name.clone() } else { format!("{} -> {}", k.old.name, k.new.name) };
+            writeln!(out, "  {name}: utility {} ({:+}), uses {} ({:+}): {}", k.new.utility, k.utility_delta, k.new.num_uses, k.num_uses_delta, k.new.body)?;
+        }
+        writeln!(out, "\nAdded ({}):", self.added.len())?;
+        for a in self.added.iter() {
+            writeln!(out, "  {}: utility {}, uses {}: {}", a.name, a.utility, a.num_uses, a.body)?;
+        }
+        writeln!(out, "\nRemoved ({}):", self.removed.len())?;
+        for a in self.removed.iter() {
+            writeln!(out, "  {}: utility {}, uses {}: {}", a.name, a.utility, a.num_uses, a.body)?;
+        }
+        match &self.programs {
+            None => writeln!(out, "\nPrograms: not compared, the runs were on different programs or left them out of the output")?,
+            Some(p) => {
+                writeln!(out, "\nRewritten cost: {} -> {} ({:+})", p.old_cost, p.new_cost, p.new_cost - p.old_cost)?;
+                writeln!(out, "Programs: {} improved, {} worsened, {} unchanged", p.num_improved, p.num_worsened, p.num_unchanged)?;
+                for (i, old_cost, new_cost) in p.changed.iter().take(top) {
+                    writeln!(out, "  program {i}: {old_cost} -> {new_cost} ({:+})", new_cost - old_cost)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for LibraryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_summary(f, 10)
+    }
+}
@@ -0,0 +1,120 @@
+use crate::*;
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+use log::{LevelFilter, Log, Metadata, Record};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+
+/// Where compression logs go. Records have a level and one of the targets
+/// - `stitch::multistep`: iteration headers, chosen abstractions and the final summary
+/// - `stitch::prep`: timings and sizes of the preprocessing at the start of each compression step
+/// - `stitch::search`: progress of the search, like new best abstractions and worklist stats
+/// - `stitch::track`: what happened to the pattern given to `--follow`
+/// - `stitch::rewrite`: problems found while rewriting
+///
+/// Everything that `--quiet` and `--silent` used to silence is still silenced by them. Library users get the same colored
+/// console output as before without doing anything, since compression installs the console logger with the default
+/// config if no logger was installed (see `init_default_logging`). Call `init_logging` (or install any other `log`
+/// logger) before compressing to change that.
+#[derive(Parser, Debug, Serialize, Clone)]
+pub struct LogConfig {
+    /// Most verbose level to print to the console (off, error, warn, info, debug or trace). Step timings and sizes are
+    /// at debug, so `info` gives just the progress of each iteration
+    #[clap(long, default_value = "debug")]
+    pub log_level: LevelFilter,
+
+    /// Also write log records to this file as JSON lines like {"time", "level", "target", "message"}, without colors
+    #[clap(long, parse(from_os_str))]
+    pub log_json: Option<PathBuf>,
+
+    /// Most verbose level to write to the --log-json file
+    #[clap(long, default_value = "debug")]
+    pub log_json_level: LevelFilter,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self::parse_from("log".split_whitespace())
+    }
+}
+
+struct Logger {
+    console_level: LevelFilter,
+    json: Option<(Mutex<LineWriter<File>>, LevelFilter)>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.console_level || matches!(&self.json, Some((_, level)) if metadata.level() <= *level)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.console_level {
+            println!("{}", record.args());
+        }
+        if let Some((file, level)) = &self.json {
+            if record.level() <= *level {
+                let line = json!({
+                    "time": chrono::Local::now().to_rfc3339(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": strip_colors(&record.args().to_string()),
+                });
+                // a log file that can't be written to shouldn't stop compression
+                let _ = writeln!(file.lock(), "{line}");
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some((file, _)) = &self.json {
+            let _ = file.lock().flush();
+        }
+    }
+}
+
+/// Install the logger for this process. Can only be called once, and not after compression has already installed the
+/// default one
+pub fn init_logging(cfg: &LogConfig) -> Result<(), StitchError> {
+    let json = match &cfg.log_json {
+        Some(path) => Some((Mutex::new(LineWriter::new(File::create(path).map_err(|e| StitchError::io(path, e))?)), cfg.log_json_level)),
+        None => None,
+    };
+    let max_level = cfg.log_level.max(json.as_ref().map(|(_, level)| *level).unwrap_or(LevelFilter::Off));
+    log::set_boxed_logger(Box::new(Logger { console_level: cfg.log_level, json }))
+        .map_err(|e| StitchError::InvalidInput(format!("failed to set up logging: {e}")))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Install the console logger with the default `LogConfig`, unless a logger is already installed. This is what makes the
+/// entrypoints of compression print to the console like they always have when the caller never set up logging.
+pub fn init_default_logging() {
+    let cfg = LogConfig::default();
+    // fails if there already is a logger, which is left alone
+    if log::set_boxed_logger(Box::new(Logger { console_level: cfg.log_level, json: None })).is_ok() {
+        log::set_max_level(cfg.log_level);
+    }
+}
+
+/// `s` without the ANSI escape codes that colorful adds
+fn strip_colors(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // skip to the end of the escape sequence, like \x1b[38;5;4m
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break
+                }
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
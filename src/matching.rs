@@ -0,0 +1,96 @@
+use lambdas::*;
+use serde::Serialize;
+
+/// A place in the corpus where a pattern matches
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternMatch {
+    pub program: usize, // index of the program in the corpus
+    pub subterm: String, // the matched subterm
+    pub args: Vec<String>, // what each ivar #i of the pattern is bound to
+}
+
+/// Every subterm of every program that `pattern` (an abstraction body with ivars like #0) matches, in program order.
+/// As when rewriting, an ivar can't be bound to a term that refers to a lambda inside the pattern, and an ivar used
+/// more than once has to be bound to the same term each time.
+pub fn find_matches(pattern: &ExprOwned, programs: &[ExprOwned]) -> Vec<PatternMatch> {
+    let arity = max_ivar(pattern.immut()).map(|i| i + 1).unwrap_or(0);
+    let mut matches = vec![];
+    for (i, program) in programs.iter().enumerate() {
+        let mut stack = vec![program.idx];
+        while let Some(node) = stack.pop() {
+            let e = program.set.get(node);
+            let mut args: Vec<Option<String>> = vec![None; arity];
+            if match_at(pattern.immut(), e, 0, &mut args) {
+                matches.push(PatternMatch {
+                    program: i,
+                    subterm: e.to_string(),
+                    args: args.into_iter().enumerate().map(|(j, arg)| arg.unwrap_or_else(|| format!("#{j}"))).collect(),
+                });
+            }
+            // pushed in reverse so matches come out in left to right order
+            stack.extend(program.set[node].children().into_iter().rev());
+        }
+    }
+    matches
+}
+
+fn max_ivar(e: Expr) -> Option<usize> {
+    match e.node() {
+        Node::IVar(i) => Some(*i as usize),
+        Node::App(f, x) => max_ivar(e.get(*f)).max(max_ivar(e.get(*x))),
+        Node::Lam(b, _) => max_ivar(e.get(*b)),
+        Node::Prim(_) | Node::Var(_, _) => None,
+    }
+}
+
+/// whether `pattern` matches `e`, binding ivars in `args`. `depth` is the number of lambdas of the pattern above this point
+fn match_at(pattern: Expr, e: Expr, depth: i32, args: &mut [Option<String>]) -> bool {
+    match (pattern.node(), e.node()) {
+        (Node::IVar(i), _) => {
+            let arg = match shifted_down(e, depth, 0) {
+                Some(arg) => arg,
+                None => return false, // refers to a lambda in the pattern
+            };
+            match &args[*i as usize] {
+                Some(bound) => *bound == arg,
+                None => { args[*i as usize] = Some(arg); true }
+            }
+        }
+        (Node::Prim(p), Node::Prim(q)) => p == q,
+        (Node::Var(i, tag), Node::Var(j, other_tag)) => i == j && tag == other_tag,
+        (Node::App(f, x), Node::App(g, y)) => match_at(pattern.get(*f), e.get(*g), depth, args) && match_at(pattern.get(*x), e.get(*y), depth, args),
+        (Node::Lam(b, tag), Node::Lam(c, other_tag)) => tag == other_tag && match_at(pattern.get(*b), e.get(*c), depth + 1, args),
+        _ => false,
+    }
+}
+
+/// `e` printed with its free variables lowered by `amount`, or None if it refers to one of the `amount` innermost
+/// lambdas above it. `depth` is the number of lambdas within `e` above this point
+fn shifted_down(e: Expr, amount: i32, depth: i32) -> Option<String> {
+    Some(match e.node() {
+        Node::Var(i, tag) if *i >= depth => {
+            if *i - depth < amount {
+                return None
+            }
+            let i = *i - amount;
+            if *tag == -1 { format!("${i}") } else { format!("${i}_{tag}") }
+        }
+        Node::Var(_, _) | Node::Prim(_) | Node::IVar(_) => e.to_string(),
+        Node::App(_, _) => {
+            // curried applications are printed flat like (f x y)
+            let mut args = vec![];
+            let mut head = e;
+            while let Node::App(f, x) = head.node() {
+                args.push(shifted_down(head.get(*x), amount, depth)?);
+                head = head.get(*f);
+            }
+            args.push(shifted_down(head, amount, depth)?);
+            args.reverse();
+            format!("({})", args.join(" "))
+        }
+        Node::Lam(b, tag) => {
+            let b = shifted_down(e.get(*b), amount, depth + 1)?;
+            if *tag == -1 { format!("(lam {b})") } else { format!("(lam_{tag} {b})") }
+        }
+    })
+}
@@ -0,0 +1,41 @@
+use crate::*;
+use std::fmt;
+
+/// Receives progress events from `multistep_compression`, e.g. to show live progress in a UI without parsing stdout.
+/// Events are sent regardless of `quiet` and `silent`. Iterations are numbered from 0 (counting resumed abstractions
+/// when resuming). `new_best` and `worklist_stats` are called from the search threads while they hold the worklist
+/// lock, so they should return quickly. Every method does nothing by default.
+pub trait Observer: Send + Sync {
+    /// The corpus has been preprocessed for this iteration and the search is about to start
+    fn prep_finished(&self, _iteration: usize, _timings: &StepTimings) {}
+
+    /// The search found an abstraction with a higher utility than any before it this iteration. Arity zero
+    /// abstractions found during prep are reported here too, once prep is finished.
+    fn new_best(&self, _iteration: usize, _utility: i32, _abstraction: &str) {}
+
+    /// Called every `worklist_stats_interval()` worklist steps during the search. Needs stats, so never called with `--no-stats`
+    fn worklist_stats(&self, _iteration: usize, _stats: &WorklistStats) {}
+
+    fn worklist_stats_interval(&self) -> usize {
+        10000
+    }
+
+    /// An abstraction was chosen and the corpus was rewritten with it
+    fn iteration_finished(&self, _iteration: usize, _result: &CompressionStepResult) {}
+}
+
+impl fmt::Debug for dyn Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observer")
+    }
+}
+
+/// A snapshot of the search, see `Observer::worklist_stats`
+#[derive(Debug, Clone)]
+pub struct WorklistStats {
+    pub worklist_steps: usize,
+    pub worklist_len: usize, // partial patterns still to expand
+    pub best_utility: Option<i32>, // of the best abstraction so far
+    pub utility_pruning_cutoff: i32,
+    pub counters: Vec<(&'static str, usize)>, // see `Stats::counters`
+}
@@ -0,0 +1,226 @@
+use clap::{ArgEnum, Parser};
+use serde::Serialize;
+use serde_json::Value;
+use itertools::Itertools;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, ArgEnum, Serialize)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// How much detail goes into a report
+#[derive(Parser, Debug, Serialize, Clone)]
+pub struct ReportConfig {
+    /// number of distinct uses to show per abstraction, most frequent first
+    #[clap(long, default_value = "5")]
+    pub top_uses: usize,
+
+    /// number of programs to show before and after rewriting per abstraction
+    #[clap(long, default_value = "3")]
+    pub examples: usize,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig { top_uses: 5, examples: 3 }
+    }
+}
+
+/// everything the report shows about one abstraction
+struct AbstractionInfo {
+    name: String,
+    arity: u64,
+    body: String,
+    utility: i64,
+    final_cost: i64,
+    compression_ratio: f64,
+    cumulative_compression_ratio: f64,
+    num_uses: u64,
+    top_uses: Vec<TopUse>,
+    examples: Vec<(String, String)>, // (original, rewritten) programs that use this abstraction
+}
+
+/// a distinct way an abstraction was called, with how many times the term it replaced appears in the corpus it was found in
+pub struct TopUse {
+    pub count: u64,
+    pub args: Vec<String>,
+    pub call: String,
+    pub expr: String,
+}
+
+/// Render the output json of a multistep compression run (as written by `compress`) as a self-contained report.
+pub fn report(out: &Value, fmt: ReportFormat, cfg: &ReportConfig) -> Result<String, String> {
+    let original_cost = out["original_cost"].as_i64().ok_or("output json has no \"original_cost\" field")?;
+    let final_cost = out["final_cost"].as_i64().ok_or("output json has no \"final_cost\" field")?;
+    let abstractions = out["abstractions"].as_array().ok_or("output json has no \"abstractions\" field")?
+        .iter().map(|abstraction| abstraction_info(abstraction, out, cfg)).collect::<Result<Vec<_>,_>>()?;
+    Ok(match fmt {
+        ReportFormat::Markdown => markdown(original_cost, final_cost, &abstractions),
+        ReportFormat::Html => html(original_cost, final_cost, &abstractions),
+    })
+}
+
+fn abstraction_info(abstraction: &Value, out: &Value, cfg: &ReportConfig) -> Result<AbstractionInfo, String> {
+    let name = abstraction["name"].as_str().ok_or_else(|| format!("abstraction without a name: {abstraction}"))?.to_string();
+
+    let originals: Vec<&str> = out["original"].as_array().map(|original| original.iter().filter_map(program_str).collect()).unwrap_or_default();
+
+    let top_uses: Vec<TopUse> = top_uses(abstraction).into_iter().take(cfg.top_uses).collect();
+
+    // programs whose final rewritten form still calls this abstraction
+    let rewritten: Vec<&str> = out["rewritten"].as_array().map(|rewritten| rewritten.iter().filter_map(program_str).collect()).unwrap_or_default();
+    let examples: Vec<(String, String)> = originals.iter().zip(rewritten.iter())
+        .filter(|(_, after)| tokens(after).any(|tok| tok == name))
+        .map(|(before, after)| (before.to_string(), after.to_string()))
+        .take(cfg.examples)
+        .collect();
+
+    Ok(AbstractionInfo {
+        body: abstraction["body"].as_str().unwrap_or_default().to_string(),
+        arity: abstraction["arity"].as_u64().unwrap_or_default(),
+        utility: abstraction["utility"].as_i64().unwrap_or_default(),
+        final_cost: abstraction["final_cost"].as_i64().unwrap_or_default(),
+        compression_ratio: abstraction["compression_ratio"].as_f64().unwrap_or_default(),
+        cumulative_compression_ratio: abstraction["cumulative_compression_ratio"].as_f64().unwrap_or_default(),
+        num_uses: abstraction["num_uses"].as_u64().unwrap_or_default(),
+        name,
+        top_uses,
+        examples,
+    })
+}
+
+/// programs in the output json are strings, or records with a "program" field for some input formats
+fn program_str(p: &Value) -> Option<&str> {
+    p.as_str().or_else(|| p["program"].as_str())
+}
+
+/// The distinct uses of an abstraction in the output json, most frequent first. "uses" is a list of {call: expr}, and
+/// "use_args" and "use_counts" hold the args of each call and how often its expr appears in the same order. The counts
+/// are those of the corpus the abstraction was found in, so uses of terms that contain earlier abstractions count too
+pub fn top_uses(abstraction: &Value) -> Vec<TopUse> {
+    let uses = abstraction["uses"].as_array().cloned().unwrap_or_default();
+    let use_args = abstraction["use_args"].as_array().cloned().unwrap_or_default();
+    let use_counts = abstraction["use_counts"].as_array().cloned().unwrap_or_default();
+    uses.iter().enumerate().filter_map(|(i, u)| {
+        let (call, expr) = u.as_object()?.iter().next()?;
+        let args: Vec<String> = use_args.get(i).and_then(|args| args.as_array())
+            .map(|args| args.iter().filter_map(|arg| arg.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default();
+        let count = use_counts.get(i).and_then(|count| count.as_u64()).unwrap_or_default();
+        Some(TopUse { count, args, call: call.clone(), expr: expr.as_str()?.to_string() })
+    })
+    .sorted_by(|a, b| b.count.cmp(&a.count).then_with(|| a.call.cmp(&b.call)))
+    .collect()
+}
+
+fn tokens(program: &str) -> impl Iterator<Item = &str> {
+    program.split(|c: char| c.is_whitespace() || c == '(' || c == ')').filter(|tok| !tok.is_empty())
+}
+
+/// (iteration, cost, cumulative compression ratio) starting from the original programs at iteration 0
+fn curve(original_cost: i64, abstractions: &[AbstractionInfo]) -> Vec<(usize, i64, f64)> {
+    std::iter::once((0, original_cost, 1.0))
+        .chain(abstractions.iter().enumerate().map(|(i, a)| (i + 1, a.final_cost, a.cumulative_compression_ratio)))
+        .collect()
+}
+
+fn markdown(original_cost: i64, final_cost: i64, abstractions: &[AbstractionInfo]) -> String {
+    // `|` would end a table cell early
+    let cell = |s: &str| s.replace('|', "\\|");
+    let mut md = String::new();
+    writeln!(md, "# Stitch compression report\n").unwrap();
+    writeln!(md, "{} abstractions, cost {original_cost} -> {final_cost} ({:.2}x)\n", abstractions.len(), original_cost as f64 / final_cost as f64).unwrap();
+
+    writeln!(md, "## Compression curve\n").unwrap();
+    writeln!(md, "| iteration | abstraction | cost | cumulative ratio | |").unwrap();
+    writeln!(md, "|---|---|---|---|---|").unwrap();
+    for (i, cost, ratio) in curve(original_cost, abstractions) {
+        let name = if i == 0 { "(original)" } else { &abstractions[i - 1].name };
+        let bar = "█".repeat((ratio * 10.0).round() as usize);
+        writeln!(md, "| {i} | {name} | {cost} | {ratio:.2}x | {bar} |").unwrap();
+    }
+    writeln!(md).unwrap();
+
+    writeln!(md, "## Abstractions\n").unwrap();
+    for a in abstractions {
+        writeln!(md, "### {}\n", a.name).unwrap();
+        writeln!(md, "```\n{}\n```\n", a.body).unwrap();
+        writeln!(md, "arity {} | utility {} | {:.2}x ({:.2}x cumulative) | {} uses | final cost {}\n",
+            a.arity, a.utility, a.compression_ratio, a.cumulative_compression_ratio, a.num_uses, a.final_cost).unwrap();
+        if !a.top_uses.is_empty() {
+            let header: Vec<String> = std::iter::once("count".to_string()).chain((0..a.arity).map(|i| format!("#{i}"))).chain(std::iter::once("matched".to_string())).collect();
+            writeln!(md, "| {} |", header.join(" | ")).unwrap();
+            writeln!(md, "|{}", "---|".repeat(header.len())).unwrap();
+            for u in a.top_uses.iter() {
+                let args: Vec<String> = if u.args.is_empty() { vec![String::new(); a.arity as usize] } else { u.args.iter().map(|arg| format!("`{}`", cell(arg))).collect() };
+                let row: Vec<String> = std::iter::once(u.count.to_string()).chain(args).chain(std::iter::once(format!("`{}`", cell(&u.expr)))).collect();
+                writeln!(md, "| {} |", row.join(" | ")).unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+        for (before, after) in a.examples.iter() {
+            writeln!(md, "```diff\n- {before}\n+ {after}\n```\n").unwrap();
+        }
+    }
+    md
+}
+
+fn html(original_cost: i64, final_cost: i64, abstractions: &[AbstractionInfo]) -> String {
+    let mut h = String::new();
+    writeln!(h, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Stitch compression report</title>").unwrap();
+    writeln!(h, "<style>body {{ font-family: sans-serif; max-width: 1100px; margin: auto; }} pre, code {{ background: #f4f4f4; white-space: pre-wrap; word-break: break-all; }} \
+        table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #ccc; padding: 2px 6px; text-align: left; vertical-align: top; }} \
+        .before {{ color: #a00; }} .after {{ color: #070; }}</style>\n</head>\n<body>").unwrap();
+    writeln!(h, "<h1>Stitch compression report</h1>").unwrap();
+    writeln!(h, "<p>{} abstractions, cost {original_cost} &rarr; {final_cost} ({:.2}x)</p>", abstractions.len(), original_cost as f64 / final_cost as f64).unwrap();
+
+    writeln!(h, "<h2>Compression curve</h2>").unwrap();
+    let points = curve(original_cost, abstractions);
+    let (width, height, pad) = (600.0, 200.0, 30.0);
+    let max_ratio = points.iter().map(|(_, _, ratio)| *ratio).fold(1.0, f64::max);
+    let x = |i: usize| pad + (width - 2.0 * pad) * i as f64 / (points.len() - 1).max(1) as f64;
+    let y = |ratio: f64| height - pad - (height - 2.0 * pad) * (ratio - 1.0) / (max_ratio - 1.0).max(1e-9);
+    writeln!(h, "<svg width=\"{width}\" height=\"{height}\" xmlns=\"http://www.w3.org/2000/svg\">").unwrap();
+    writeln!(h, "<line x1=\"{pad}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/>", height - pad, width - pad, height - pad).unwrap();
+    writeln!(h, "<line x1=\"{pad}\" y1=\"{pad}\" x2=\"{pad}\" y2=\"{}\" stroke=\"#999\"/>", height - pad).unwrap();
+    writeln!(h, "<text x=\"2\" y=\"{}\" font-size=\"10\">{max_ratio:.2}x</text><text x=\"2\" y=\"{}\" font-size=\"10\">1x</text>", pad, height - pad).unwrap();
+    writeln!(h, "<polyline fill=\"none\" stroke=\"#36c\" stroke-width=\"2\" points=\"{}\"/>",
+        points.iter().map(|(i, _, ratio)| format!("{:.1},{:.1}", x(*i), y(*ratio))).join(" ")).unwrap();
+    for (i, cost, ratio) in points.iter() {
+        writeln!(h, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"#36c\"><title>iteration {i}: cost {cost}, {ratio:.2}x</title></circle>", x(*i), y(*ratio)).unwrap();
+    }
+    writeln!(h, "</svg>").unwrap();
+    writeln!(h, "<table>\n<tr><th>iteration</th><th>abstraction</th><th>cost</th><th>cumulative ratio</th></tr>").unwrap();
+    for (i, cost, ratio) in points.iter() {
+        let name = if *i == 0 { "(original)" } else { &abstractions[i - 1].name };
+        writeln!(h, "<tr><td>{i}</td><td>{}</td><td>{cost}</td><td>{ratio:.2}x</td></tr>", escape(name)).unwrap();
+    }
+    writeln!(h, "</table>").unwrap();
+
+    writeln!(h, "<h2>Abstractions</h2>").unwrap();
+    for a in abstractions {
+        writeln!(h, "<h3 id=\"{0}\">{0}</h3>", escape(&a.name)).unwrap();
+        writeln!(h, "<pre>{}</pre>", escape(&a.body)).unwrap();
+        writeln!(h, "<p>arity {} | utility {} | {:.2}x ({:.2}x cumulative) | {} uses | final cost {}</p>",
+            a.arity, a.utility, a.compression_ratio, a.cumulative_compression_ratio, a.num_uses, a.final_cost).unwrap();
+        if !a.top_uses.is_empty() {
+            writeln!(h, "<table>\n<tr><th>count</th>{}<th>matched</th></tr>", (0..a.arity).map(|i| format!("<th>#{i}</th>")).join("")).unwrap();
+            for u in a.top_uses.iter() {
+                let args = if u.args.is_empty() { vec![String::new(); a.arity as usize] } else { u.args.iter().map(|arg| format!("<code>{}</code>", escape(arg))).collect() };
+                writeln!(h, "<tr><td>{}</td>{}<td><code>{}</code></td></tr>", u.count, args.iter().map(|arg| format!("<td>{arg}</td>")).join(""), escape(&u.expr)).unwrap();
+            }
+            writeln!(h, "</table>").unwrap();
+        }
+        for (before, after) in a.examples.iter() {
+            writeln!(h, "<pre><span class=\"before\">- {}</span>\n<span class=\"after\">+ {}</span></pre>", escape(before), escape(after)).unwrap();
+        }
+    }
+    writeln!(h, "</body>\n</html>").unwrap();
+    h
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,6 +1,7 @@
 use crate::*;
 use lambdas::*;
 use compression::*;
+use log::error;
 
 /// a rule for determining when to shift and by how much.
 /// if anything points above the `depth_cutoff` (absolute depth
@@ -17,7 +18,7 @@ pub fn rewrite_fast(
     shared: &SharedData,
     inv_name: &Node,
     cost_fn: &ExprCost
-) -> Vec<ExprOwned>
+) -> Result<Vec<ExprOwned>, StitchError>
 {
     //  if !shared.cfg.quiet { println!("rewriting with {}", pattern.info(&shared)) }
     #[allow(clippy::too_many_arguments)]
@@ -44,7 +45,7 @@ pub fn rewrite_fast(
             //  if !shared.cfg.quiet { println!("inv applies at unshifted={} with shift={}", extract(unshifted_id,&shared.egraph), shift) }
             let mut expr = owned_set.add(inv_name.clone());
             // wrap the prim in all the Apps to args
-            for (_ivar,zid) in pattern.pattern.first_zid_of_ivar.iter().enumerate() {
+            for zid in pattern.pattern.first_zid_of_ivar.iter() {
                 let arg: &Arg = &shared.arg_of_zid_node[*zid][&unshifted_id];
 
                 if arg.shift != 0 {
@@ -142,16 +143,17 @@ pub fn rewrite_fast(
     }).collect();
 
     if !shared.cfg.no_mismatch_check && !shared.cfg.utility_by_rewrite {
-        assert_eq!(
-            shared.root_idxs_of_task.iter().map(|root_idxs|
-                root_idxs.iter().map(|idx| (rewritten_exprs[*idx].cost(cost_fn) as f32 * shared.weight_by_root_idx[*idx]).round() as i32).min().unwrap()
-            ).sum::<i32>(),
-            shared.init_cost_weighted - pattern.util_calc.util,
-            "\n{}\n", pattern.info(shared)
-        );
+        let actual = shared.root_idxs_of_task.iter().map(|root_idxs|
+            root_idxs.iter().map(|idx| (rewritten_exprs[*idx].cost(cost_fn) as f32 * shared.weight_by_root_idx[*idx]).round() as i32).min().unwrap()
+        ).sum::<i32>();
+        let expected = shared.init_cost_weighted - pattern.util_calc.util;
+        if actual != expected {
+            if !shared.cfg.quiet { error!(target: "stitch::rewrite", "\n{}\n", pattern.info(shared)) }
+            return Err(StitchError::CostMismatch { abstraction: pattern.to_expr(shared).to_string(), expected, actual })
+        }
     }
 
-    rewritten_exprs
+    Ok(rewritten_exprs)
 }
 
 /// Rewrite with the given abstractions by performing a ultra heavily pruned version of the compression search
@@ -160,7 +162,29 @@ pub fn rewrite_with_inventions(
     programs: &[String],
     invs: &[Invention],
     cfg: &MultistepCompressionConfig,
-) -> (Vec<String>, Vec<CompressionStepResult>, serde_json::Value) {
+) -> Result<(Vec<String>, Vec<CompressionStepResult>, serde_json::Value), StitchError> {
+    let (step_results, json_res) = multistep_compression(programs, None, None, None, None, Some(invs.to_vec()), None, &rewrite_cfg(invs, cfg))?;
+
+    // return the last one - note that if an abstraction wasn't used anywhere it will not be included in the step_results so this
+    // may be shorter than invs.len(), however we do ensure that we continue searching for the rest of the abstractions if this happens
+    // anyways.
+    let rewritten = step_results.last().map(|res|res.rewritten.iter().map(|s|s.to_string()).collect()).unwrap_or_else(||programs.to_vec());
+    Ok((rewritten, step_results, json_res))
+}
+
+/// `rewrite_with_inventions` on programs that were already parsed
+pub fn rewrite_with_inventions_parsed(
+    programs: &[ExprOwned],
+    invs: &[Invention],
+    cfg: &MultistepCompressionConfig,
+) -> Result<(Vec<String>, Vec<CompressionStepResult>, serde_json::Value), StitchError> {
+    let (step_results, json_res) = multistep_compression_parsed(programs, None, None, None, None, Some(invs.to_vec()), None, &rewrite_cfg(invs, cfg))?;
+    let rewritten = step_results.last().map_or(programs, |res| &res.rewritten).iter().map(|s|s.to_string()).collect();
+    Ok((rewritten, step_results, json_res))
+}
+
+/// the config for compression that only follows `invs`, one per iteration
+fn rewrite_cfg(invs: &[Invention], cfg: &MultistepCompressionConfig) -> MultistepCompressionConfig {
 
     // if invs.is_empty() {
     //     return programs.to_vec()
@@ -169,12 +193,14 @@ pub fn rewrite_with_inventions(
     let mut cfg = cfg.clone();
 
     // programs.to_vec()
-    let follow = Some(invs.to_vec());
     cfg.iterations = invs.len();
-    cfg.step.max_arity = invs.iter().map(|inv| inv.arity).max().unwrap();
+    cfg.step.max_arity = invs.iter().map(|inv| inv.arity).max().unwrap_or(0);
     cfg.silent = true;
     cfg.no_opt = true;
     cfg.step.allow_single_task = true;
+    // the rewritten programs have to line up with the ones passed in
+    cfg.shuffle = false;
+    cfg.truncate = None;
 
     if cfg.verbose_rewrite {
         cfg.silent = false;
@@ -185,11 +211,34 @@ pub fn rewrite_with_inventions(
     // cfg.step.rewritten_dreamcoder = true;
     // cfg.step.rewritten_intermediates = true;
 
-    let (step_results, json_res) = multistep_compression(programs, None, None, None, follow, &cfg);
+    cfg
+}
+/// A DreamCoder-format json with its programs replaced by `rewritten` (the output of `rewrite_with_inventions`). The result
+/// has exactly the structure of `dc_json`, with the same frontiers in the same order and every field kept, and only the
+/// "program" strings replaced. `input` is what `InputFormat::Dreamcoder` loaded from `dc_json` and `inv_dreamcoder` are the
+/// #(lambda ...) forms of `invs`. If `dreamcoder_output` is set, inventions in the rewritten programs are written in
+/// #(lambda ...) form, otherwise by name.
+pub fn rewritten_dreamcoder_json(
+    dc_json: &serde_json::Value,
+    input: &Input,
+    rewritten: &[String],
+    invs: &[Invention],
+    inv_dreamcoder: &[String],
+    dreamcoder_output: bool,
+) -> Result<serde_json::Value, StitchError> {
+    let mut name_mapping = input.name_mapping.clone().unwrap_or_default();
+    name_mapping.extend(invs.iter().map(|inv| inv.name.clone()).zip(inv_dreamcoder.iter().cloned()));
+    if !dreamcoder_output {
+        name_mapping.clear();
+    }
 
-    // return the last one - note that if an abstraction wasn't used anywhere it will not be included in the step_results so this
-    // may be shorter than invs.len(), however we do ensure that we continue searching for the rest of the abstractions if this happens
-    // anyways.
-    let rewritten = step_results.last().map(|res|res.rewritten.iter().map(|s|s.to_string()).collect()).unwrap_or_else(||programs.to_vec());
-    (rewritten, step_results, json_res)
-}
\ No newline at end of file
+    let rewritten: Vec<String> = rewritten.iter().enumerate().map(|(i,p)| {
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(p).map_err(|e| StitchError::parse(Some(i), p, e))?;
+        Ok(dreamcoder_string(set.get(idx), &name_mapping))
+    }).collect::<Result<_,StitchError>>()?;
+
+    let mut json = dc_json.clone();
+    json["frontiers"] = dreamcoder_frontiers_with_programs(dc_json, &rewritten).map_err(StitchError::InvalidInput)?.into();
+    Ok(json)
+}
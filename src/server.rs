@@ -0,0 +1,296 @@
+use crate::*;
+use lambdas::*;
+use clap::{ArgEnum, Parser};
+use serde_json::{json, Value};
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const STITCH_ERROR: i64 = -32000;
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// The corpus that the `serve` binary keeps loaded between requests
+pub struct Corpus {
+    pub input: Input,
+    pub programs: Vec<ExprOwned>, // the programs that parsed
+    pub indices: Vec<usize>, // index of each parsed program in `input.train_programs`
+    pub invalid_programs: Vec<ProgramError>,
+}
+
+impl Corpus {
+    pub fn new(input: Input) -> Self {
+        let (parsed, mut invalid_programs) = parse_programs(&input.train_programs, input.tasks.as_deref());
+        input.locate(&mut invalid_programs);
+        let (indices, programs) = parsed.into_iter().unzip();
+        Corpus { input, programs, indices, invalid_programs }
+    }
+
+    /// the tasks of the programs that parsed
+    fn tasks(&self) -> Option<Vec<String>> {
+        self.input.tasks.as_ref().map(|tasks| self.indices.iter().map(|i| tasks[*i].clone()).collect())
+    }
+
+    /// the weights of the programs that parsed
+    fn weights(&self) -> Option<Vec<f32>> {
+        self.input.weights.as_ref().map(|weights| self.indices.iter().map(|i| weights[*i]).collect())
+    }
+
+    /// like compressing the program strings, programs that didn't parse are an error unless `cfg.skip_invalid` is set
+    fn check_valid(&self, cfg: &MultistepCompressionConfig) -> Result<(), StitchError> {
+        if self.invalid_programs.is_empty() || cfg.skip_invalid {
+            return Ok(())
+        }
+        Err(StitchError::InvalidPrograms(self.invalid_programs.clone()))
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "num_programs": self.input.train_programs.len(),
+            "invalid_programs": self.invalid_programs,
+        })
+    }
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Display) -> Self {
+        RpcError { code: INVALID_PARAMS, message: message.to_string() }
+    }
+}
+
+impl From<StitchError> for RpcError {
+    fn from(e: StitchError) -> Self {
+        let code = if let StitchError::Cancelled = e { REQUEST_CANCELLED } else { STITCH_ERROR };
+        RpcError { code, message: e.to_string() }
+    }
+}
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Line-delimited JSON-RPC 2.0 over a pair of streams, answering each request line with one response line. Methods:
+/// - `load` {"file", "fmt"} or {"programs", "tasks", "weights"}: replace the corpus
+/// - `compress` {"args"}: run compression on the corpus with `compress`-style flags, returning its output json
+/// - `rewrite` {"abstractions", "args"}: rewrite the corpus with abstractions like those in an output json
+/// - `match` {"pattern"}: every place in the corpus that a pattern like (foo #0 #1) matches
+/// - `stats` {"top", "args"}: corpus statistics as in the `stats` binary, under the cost flags in "args"
+/// - `cancel` {"id"}: stop the `compress` or `rewrite` request with this id, which then fails with code -32800
+///
+/// `compress` and `rewrite` run in the background so they can be cancelled, and their responses may come out of order.
+/// Since they're cancelled by id, a request can't reuse the id of one that's still running.
+pub struct Server {
+    corpus: Option<Arc<Corpus>>,
+    tree_cfg: TreeConfig,
+    running: Arc<Mutex<FxHashMap<String, Cancellation>>>, // by request id
+    out: Output,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    /// `tree_cfg` is used when `load` reads a json-tree file
+    pub fn new(corpus: Option<Corpus>, tree_cfg: TreeConfig, out: impl Write + Send + 'static) -> Self {
+        Server {
+            corpus: corpus.map(Arc::new),
+            tree_cfg,
+            running: Default::default(),
+            out: Arc::new(Mutex::new(Box::new(out))),
+            workers: vec![],
+        }
+    }
+
+    /// Handle one request. Requests without an id are notifications and get no response
+    pub fn handle_line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return
+        }
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return respond(&self.out, &Value::Null, Err(RpcError { code: PARSE_ERROR, message: e.to_string() })),
+        };
+        let id: Option<Value> = request.get("id").cloned();
+        let method = match request["method"].as_str() {
+            Some(method) => method.to_string(),
+            None => return respond(&self.out, &id.unwrap_or_default(), Err(RpcError { code: INVALID_REQUEST, message: "no \"method\" field".to_string() })),
+        };
+        let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+
+        if method == "compress" || method == "rewrite" {
+            return self.spawn(id, method, params)
+        }
+        let res = match method.as_str() {
+            "load" => self.load(&params),
+            "match" => self.find_matches(&params),
+            "stats" => self.stats(&params),
+            "cancel" => self.cancel(&params),
+            _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("unknown method {method:?}") }),
+        };
+        if let Some(id) = id {
+            respond(&self.out, &id, res)
+        }
+    }
+
+    /// Wait for any running `compress` and `rewrite` requests to finish
+    pub fn wait(&mut self) {
+        for worker in self.workers.drain(..) {
+            // workers catch their own panics, so this can't fail
+            let _ = worker.join();
+        }
+    }
+
+    fn corpus(&self) -> Result<Arc<Corpus>, RpcError> {
+        self.corpus.clone().ok_or_else(|| RpcError::invalid_params("no corpus loaded, call \"load\" first"))
+    }
+
+    /// run `compress` or `rewrite` on another thread
+    fn spawn(&mut self, id: Option<Value>, method: String, params: Value) {
+        let setup = || -> Result<(Arc<Corpus>, MultistepCompressionConfig), RpcError> {
+            let args = params["args"].as_str().unwrap_or_default();
+            let mut cfg = MultistepCompressionConfig::from_args(std::iter::once("compress").chain(args.split_whitespace())).map_err(RpcError::invalid_params)?;
+            // stdout is for responses only
+            cfg.silent = true;
+            cfg.verbose_rewrite = false;
+            Ok((self.corpus()?, cfg))
+        };
+        let (corpus, cfg) = match setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                if let Some(id) = id {
+                    respond(&self.out, &id, Err(e))
+                }
+                return
+            }
+        };
+        let key = id.as_ref().map(|id| id.to_string());
+        if let Some(key) = &key {
+            let mut running = self.running.lock();
+            if running.contains_key(key) {
+                drop(running);
+                return respond(&self.out, id.as_ref().unwrap(), Err(RpcError { code: INVALID_REQUEST, message: format!("request {key} is still running, its id can't be reused until it finishes") }))
+            }
+            running.insert(key.clone(), cfg.cancel.clone());
+        }
+        let running = Arc::clone(&self.running);
+        let out = Arc::clone(&self.out);
+        self.workers.retain(|worker| !worker.is_finished());
+        self.workers.push(thread::spawn(move || {
+            // a panic in compression is reported as an error of this request rather than taking down the server
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                if method == "compress" { compress(&corpus, &cfg) } else { rewrite(&corpus, &params, &cfg) }
+            })).unwrap_or_else(|e| {
+                let message = e.downcast_ref::<String>().map(|s| s.as_str()).or_else(|| e.downcast_ref::<&str>().copied()).unwrap_or("unknown panic");
+                Err(RpcError { code: STITCH_ERROR, message: format!("{method} panicked: {message}") })
+            });
+            if let (Some(id), Some(key)) = (id, key) {
+                running.lock().remove(&key);
+                respond(&out, &id, res)
+            }
+        }));
+    }
+
+    fn load(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let input = if let Some(file) = params["file"].as_str() {
+            let fmt = InputFormat::from_str(params["fmt"].as_str().unwrap_or("programs-list"), true).map_err(RpcError::invalid_params)?;
+            fmt.load_programs_and_tasks_cfg(Path::new(file), &self.tree_cfg)?
+        } else {
+            let field = |key: &str| -> Result<Option<Vec<String>>, RpcError> {
+                if params[key].is_null() {
+                    return Ok(None)
+                }
+                serde_json::from_value(params[key].clone()).map(Some).map_err(|e| RpcError::invalid_params(format!("\"{key}\" should be a list of strings: {e}")))
+            };
+            let train_programs = field("programs")?.ok_or_else(|| RpcError::invalid_params("expected a \"file\" or \"programs\" field"))?;
+            let tasks = field("tasks")?;
+            let weights: Option<Vec<f32>> = if params["weights"].is_null() { None } else {
+                Some(serde_json::from_value(params["weights"].clone()).map_err(|e| RpcError::invalid_params(format!("\"weights\" should be a list of numbers: {e}")))?)
+            };
+            Input { train_programs, tasks, weights, name_mapping: None, inventions: None, records: None, tree: None, lines: None }
+        };
+        let corpus = Corpus::new(input);
+        let summary = corpus.summary();
+        self.corpus = Some(Arc::new(corpus));
+        Ok(summary)
+    }
+
+    fn find_matches(&self, params: &Value) -> Result<Value, RpcError> {
+        let corpus = self.corpus()?;
+        let pattern = params["pattern"].as_str().ok_or_else(|| RpcError::invalid_params("expected a \"pattern\" field"))?;
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(pattern).map_err(|e| StitchError::parse(None, pattern, e))?;
+        let mut matches = find_matches(&ExprOwned::new(set, idx), &corpus.programs);
+        // back to indices into the whole corpus, counting the programs that didn't parse
+        for m in matches.iter_mut() {
+            m.program = corpus.indices[m.program];
+        }
+        Ok(json!({ "matches": matches }))
+    }
+
+    fn stats(&self, params: &Value) -> Result<Value, RpcError> {
+        let corpus = self.corpus()?;
+        let cost: CostConfig = parse_args("stats", params)?;
+        let top = params["top"].as_u64().unwrap_or(10) as usize;
+        let stats = CorpusStats::new(&corpus.programs, corpus.tasks().as_deref(), &cost.expr_cost(), top);
+        Ok(json!(stats))
+    }
+
+    fn cancel(&self, params: &Value) -> Result<Value, RpcError> {
+        let key = params.get("id").ok_or_else(|| RpcError::invalid_params("expected the \"id\" of the request to cancel"))?.to_string();
+        let cancelled = match self.running.lock().get(&key) {
+            Some(cancel) => { cancel.cancel(); true }
+            None => false, // already finished, or never existed
+        };
+        Ok(json!({ "cancelled": cancelled }))
+    }
+}
+
+fn compress(corpus: &Corpus, cfg: &MultistepCompressionConfig) -> Result<Value, RpcError> {
+    corpus.check_valid(cfg)?;
+    let (_, mut json_res) = multistep_compression_parsed(&corpus.programs, corpus.tasks(), corpus.weights(), corpus.input.name_mapping.clone(), corpus.input.inventions.clone(), None, None, cfg)?;
+    if cfg.skip_invalid {
+        json_res["invalid_programs"] = json!(corpus.invalid_programs);
+    }
+    Ok(json_res)
+}
+
+fn rewrite(corpus: &Corpus, params: &Value, cfg: &MultistepCompressionConfig) -> Result<Value, RpcError> {
+    let abstractions = params["abstractions"].as_array().ok_or_else(|| RpcError::invalid_params("expected an \"abstractions\" list"))?;
+    let inventions: Vec<Invention> = abstractions.iter().enumerate().map(|(i, a)| {
+        let field = |key: &str| a[key].as_str().ok_or_else(|| RpcError::invalid_params(format!("abstraction {i} has no string \"{key}\" field")));
+        let body = field("body")?;
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(body).map_err(|e| StitchError::parse(None, body, e))?;
+        let arity = a["arity"].as_u64().ok_or_else(|| RpcError::invalid_params(format!("abstraction {i} has no integer \"arity\" field")))?;
+        Ok(Invention::new(ExprOwned::new(set, idx), arity as usize, field("name")?))
+    }).collect::<Result<_, RpcError>>()?;
+    corpus.check_valid(cfg)?;
+    let (rewritten, _, _) = rewrite_with_inventions_parsed(&corpus.programs, &inventions, cfg)?;
+    Ok(json!({ "rewritten": rewritten }))
+}
+
+/// a config from the command line style flags in the "args" field of `params`, like "-a3 -i10"
+fn parse_args<T: Parser>(name: &str, params: &Value) -> Result<T, RpcError> {
+    let args = params["args"].as_str().unwrap_or_default();
+    T::try_parse_from(std::iter::once(name).chain(args.split_whitespace())).map_err(RpcError::invalid_params)
+}
+
+fn respond(out: &Output, id: &Value, res: Result<Value, RpcError>) {
+    let response = match res {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } }),
+    };
+    let mut out = out.lock();
+    // nothing to do if the client went away
+    let _ = writeln!(out, "{response}").and_then(|_| out.flush());
+}
@@ -0,0 +1,199 @@
+//! Conversion between generic JSON syntax trees and stitch programs, so that ASTs from other language
+//! frontends (Python, Lisp, ...) can be compressed directly.
+//!
+//! # Encoding
+//!
+//! A tree is either an object `{"type": T, "children": [c1, ..., cn]}` or a nested array `[T, c1, ..., cn]`, and
+//! becomes the curried application `(T c1 ... cn)` of the primitive `T`. Nodes without children (a missing or empty
+//! "children" field, a one element array, or a bare string/number/bool in the array form) become the primitive `T`.
+//!
+//! With arity tags on (`--tree-arity-tags`), every primitive gets its number of children appended after a
+//! separator (`/` by default, see `--tree-arity-sep`), so `{"type": "Call", "children": [f, x]}` becomes `(Call/2 f x)`
+//! and a `Call` with one child can never be confused with a partially applied `Call` with two children.
+//!
+//! Characters that mean something to the program parser are escaped in primitive names as `%XX` (hex): whitespace,
+//! parentheses, `%` itself, a leading `$` or `#`, and the `l` of names that would read as a lambda (`lam`, `lambda`,
+//! `lam_*`, `lambda_*`). The empty string becomes `%`.
+//!
+//! Exporting reverses this. Lambdas in abstraction bodies become `{"type": "lambda", "children": [body]}`, and
+//! variables and abstraction arguments become leaves named `$i` and `#i`. An application whose head isn't a primitive
+//! (which stitch never produces in a rewritten program, but can in principle) becomes `{"type": "app", "children": [f, x...]}`.
+
+use clap::Parser;
+use serde::Serialize;
+use serde_json::{json, Value};
+use lambdas::*;
+use crate::validation::is_lambda_token;
+
+/// How trees are turned into primitives, see the module docs
+#[derive(Parser, Debug, Serialize, Clone)]
+pub struct TreeConfig {
+    /// for --fmt json-tree: append each node's number of children to its primitive name, e.g. `Call/2`
+    #[clap(long)]
+    pub tree_arity_tags: bool,
+
+    /// for --fmt json-tree: the separator between a node type and its arity when --tree-arity-tags is set
+    #[clap(long, default_value = "/")]
+    pub tree_arity_sep: String,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        TreeConfig { tree_arity_tags: false, tree_arity_sep: "/".to_string() }
+    }
+}
+
+/// Which of the two tree shapes a file used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TreeShape {
+    Objects,
+    Arrays,
+}
+
+/// Everything needed to turn programs back into trees of the same shape they were loaded from
+#[derive(Debug, Clone)]
+pub struct TreeEncoding {
+    pub shape: TreeShape,
+    pub cfg: TreeConfig,
+}
+
+/// shape of the first tree, defaulting to objects when there are no trees
+pub fn tree_shape(trees: &[Value]) -> TreeShape {
+    match trees.first() {
+        Some(Value::Object(_)) | None => TreeShape::Objects,
+        Some(_) => TreeShape::Arrays,
+    }
+}
+
+/// the program string for a tree
+pub fn program_of_tree(tree: &Value, cfg: &TreeConfig) -> Result<String, String> {
+    let (ty, children): (String, Vec<&Value>) = match tree {
+        Value::Object(obj) => {
+            let ty = match obj.get("type") {
+                Some(Value::String(ty)) => ty.clone(),
+                Some(ty) if !ty.is_null() && !ty.is_array() && !ty.is_object() => ty.to_string(),
+                _ => return Err(format!("tree node has no \"type\" field: {tree}")),
+            };
+            let children = match obj.get("children") {
+                None | Some(Value::Null) => vec![],
+                Some(Value::Array(children)) => children.iter().collect(),
+                Some(children) => return Err(format!("\"children\" of a tree node must be an array, got: {children}")),
+            };
+            (ty, children)
+        }
+        Value::Array(arr) => {
+            let ty = match arr.first() {
+                Some(Value::String(ty)) => ty.clone(),
+                _ => return Err(format!("nested array tree node must start with its type as a string: {tree}")),
+            };
+            (ty, arr[1..].iter().collect())
+        }
+        Value::String(leaf) => (leaf.clone(), vec![]),
+        Value::Null => return Err("null is not a valid tree node".to_string()),
+        leaf => (leaf.to_string(), vec![]),
+    };
+    let mut prim = escape_prim(&ty);
+    if cfg.tree_arity_tags {
+        prim = format!("{prim}{}{}", cfg.tree_arity_sep, children.len());
+    }
+    if children.is_empty() {
+        return Ok(prim)
+    }
+    let children: Vec<String> = children.into_iter().map(|child| program_of_tree(child, cfg)).collect::<Result<_,_>>()?;
+    Ok(format!("({prim} {})", children.join(" ")))
+}
+
+/// the tree for a program string, e.g. a rewritten program or an abstraction body
+pub fn tree_of_program(program: &str, enc: &TreeEncoding) -> Result<Value, String> {
+    let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+    let idx = set.parse_extend(program)?;
+    Ok(tree_of_expr(set.get(idx), enc))
+}
+
+/// the tree for an expression
+pub fn tree_of_expr(e: Expr, enc: &TreeEncoding) -> Value {
+    // unroll the application spine
+    let mut args: Vec<Expr> = vec![];
+    let mut head = e;
+    while let Node::App(f,x) = head.node() {
+        args.push(head.get(*x));
+        head = head.get(*f);
+    }
+    args.reverse();
+    let children: Vec<Value> = args.into_iter().map(|arg| tree_of_expr(arg, enc)).collect();
+
+    let (ty, children) = match head.node() {
+        Node::Prim(p) => (prim_type(&p.to_string(), enc), children),
+        Node::Var(i, _) => (format!("${i}"), children),
+        Node::IVar(i) => (format!("#{i}"), children),
+        Node::Lam(b, _) => {
+            let lam = make_node("lambda".to_string(), vec![tree_of_expr(head.get(*b), enc)], enc.shape);
+            if children.is_empty() {
+                return lam
+            }
+            ("app".to_string(), std::iter::once(lam).chain(children).collect())
+        }
+        Node::App(_,_) => unreachable!(),
+    };
+    make_node(ty, children, enc.shape)
+}
+
+fn make_node(ty: String, children: Vec<Value>, shape: TreeShape) -> Value {
+    match shape {
+        TreeShape::Objects => json!({"type": ty, "children": children}),
+        TreeShape::Arrays if children.is_empty() => Value::String(ty),
+        TreeShape::Arrays => Value::Array(std::iter::once(Value::String(ty)).chain(children).collect()),
+    }
+}
+
+/// the node type of a primitive, dropping its arity tag if it has one. Primitives that didn't come from a tree
+/// (like abstraction names `fn_i`) have no tag and are left alone.
+fn prim_type(prim: &str, enc: &TreeEncoding) -> String {
+    let mut prim = prim;
+    if enc.cfg.tree_arity_tags {
+        if let Some((ty, arity)) = prim.rsplit_once(enc.cfg.tree_arity_sep.as_str()) {
+            if !arity.is_empty() && arity.chars().all(|c| c.is_ascii_digit()) {
+                prim = ty;
+            }
+        }
+    }
+    unescape_prim(prim)
+}
+
+fn escape_prim(ty: &str) -> String {
+    if ty.is_empty() {
+        return "%".to_string()
+    }
+    let lambda_like = is_lambda_token(ty);
+    ty.chars().enumerate().map(|(i,c)| {
+        let escape = c.is_whitespace() || c == '(' || c == ')' || c == '%'
+            || (i == 0 && (c == '$' || c == '#' || lambda_like));
+        if escape {
+            let mut buf = [0; 4];
+            c.encode_utf8(&mut buf).bytes().map(|b| format!("%{b:02X}")).collect()
+        } else {
+            c.to_string()
+        }
+    }).collect()
+}
+
+fn unescape_prim(prim: &str) -> String {
+    if prim == "%" {
+        return String::new()
+    }
+    let bytes = prim.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8(&bytes[i+1..i+3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
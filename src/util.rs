@@ -1,5 +1,7 @@
-use crate::*;
 use lambdas::*;
+use log::info;
+use std::path::Path;
+use crate::error::StitchError;
 
 
 pub fn min_cost(programs: &[ExprOwned], weights: &Option<Vec<f32>>, tasks: &Option<Vec<String>>, cost_fn: &ExprCost) -> i32 {
@@ -17,14 +19,24 @@ pub fn min_cost(programs: &[ExprOwned], weights: &Option<Vec<f32>>, tasks: &Opti
     }
 }
 
-/// print some info about a Vec of programs
+/// log some info about a Vec of programs
 pub fn programs_info(programs: &[ExprOwned], cost_fn: &ExprCost) {
     let max_cost = programs.iter().map(|e| e.cost(cost_fn)).max().unwrap();
     let max_depth = programs.iter().map(|e| e.depth()).max().unwrap();
-    println!("Programs:");
-    println!("\t num: {}",programs.len());
-    println!("\t max cost: {max_cost}");
-    println!("\t max depth: {max_depth}"); 
+    info!(target: "stitch::multistep", "Programs:");
+    info!(target: "stitch::multistep", "\t num: {}",programs.len());
+    info!(target: "stitch::multistep", "\t max cost: {max_cost}");
+    info!(target: "stitch::multistep", "\t max depth: {max_depth}"); 
+}
+
+/// write `contents` to `path`, creating any missing parent directories, as the binaries do for all their output files
+pub fn write_output(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), StitchError> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() && !dir.exists() {
+            std::fs::create_dir_all(dir).map_err(|e| StitchError::io(dir, e))?;
+        }
+    }
+    std::fs::write(path, contents).map_err(|e| StitchError::io(path, e))
 }
 
 /// provides a timestamp as a string in a format you can use for file/folder names: YYYY-MM-DD_HH-MM-SS
@@ -33,68 +45,16 @@ pub fn timestamp() -> String {
 }
 
 
-pub fn compression_factor(original: i32, compressed: i32) -> f64 {
-    f64::from(original)/f64::from(compressed)
-}
-
-/// Replace the ivars in an expr with vars
-pub fn ivar_to_dc(e: &mut ExprMut, depth: i32, arity: i32) {
-    match e.node().clone() {
-        // dreamcoder doesn't care about the tags, so just use -1 for all of them
-        Node::IVar(i) => *e.node() = Node::Var(depth + (arity - 1 - i), -1), // the higher the ivar the smaller the var
-        Node::Var(_, _) => {},
-        Node::Prim(_) => {},
-        Node::App(f,x) => {
-            ivar_to_dc(&mut e.get(f), depth, arity);
-            ivar_to_dc(&mut e.get(x), depth, arity);
-        },
-        Node::Lam(b, _) => {
-            ivar_to_dc(&mut e.get(b), depth+1, arity);
-        },
-    }
+/// The peak resident set size of this process so far in KB (VmHWM in /proc/self/status), or None if that isn't
+/// available, e.g. when not on Linux
+pub fn peak_rss_kb() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()
 }
 
-pub fn dc_inv_str(inv: &Invention, dreamcoder_translations: &[(String, String)]) -> String {
-    let mut body = inv.body.clone();
-    assert!(body.set.struct_hash.is_none()); // we dont attempt to maintain the struct hash
-    ivar_to_dc(&mut body.as_mut(), 0, inv.arity as i32);
-
-    // wrap in lambdas for dremacoder
-    for _ in 0..inv.arity {
-        // dreamcoder doesn't care about the tags, so just use -1 for all of them
-        body.idx = body.set.add(Node::Lam(body.idx, -1));
-    }
-    // add the "#" that dreamcoder wants and change lam -> lambda
-    let mut res: String = format!("#{body}");
-    res = res.replace("(lam ", "(lambda ").replace("(lam_", "(lambda_");
-    // inline any past inventions using their dc_inv_str. Match on "fn_i)" and "fn_i " to avoid matching fn_1 on fn_10 or any other prefix
-    for (inv_name, dc_translation) in dreamcoder_translations.iter() {
-        res = replace_prim_with(&res, inv_name, dc_translation);
-        // res = res.replace(&format!("{})",past_step_result.inv.name), &format!("{})",past_step_result.dc_inv_str));
-        // res = res.replace(&format!("{} ",past_step_result.inv.name), &format!("{} ", past_step_result.dc_inv_str));
-    }
-    res
-}
-
-pub fn replace_prim_with(s: &str, prim: &str, new: &str) -> String {
-    let mut res: String = s.to_string();
-    res = res.replace(&format!(" {prim})"), &format!(" {new})"));
-    // we need to do the " {} " case twice to handle multioverlaps like fn_i fn_i fn_i fn_i which will replace at locations 1 and 3
-    // in the first replace() and 2 and 4 in the second replace due to overlapping matches.
-    res = res.replace(&format!(" {prim} "), &format!(" {new} "));
-    res = res.replace(&format!(" {prim} "), &format!(" {new} "));
-    assert!(!res.contains(&format!(" {prim} ")));
-    res = res.replace(&format!("({prim} "), &format!("({new} "));
-    if res.starts_with(&format!("{prim} ")) {
-        res = format!("{} {}", new, &res[prim.len()..]);
-    }
-    if res.ends_with(&format!(" {prim}")) {
-        res = format!("{} {}", &res[..res.len()-prim.len()], new);
-    }
-    if res == prim {
-        res = new.to_string();
-    }
-    res
+pub fn compression_factor(original: i32, compressed: i32) -> f64 {
+    f64::from(original)/f64::from(compressed)
 }
 
 /// Returns a vec from node Idx to number of places that node is used in the tree. Essentially this just
@@ -0,0 +1,285 @@
+use lambdas::*;
+use clap::Parser;
+use serde::Serialize;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fmt;
+use crate::compression::FusedLambdaTags;
+
+/// A program that failed to parse, with enough context to find and fix it in a large corpus
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramError {
+    pub index: usize, // position of the program in the corpus
+    pub line: Option<usize>, // line of the input file the program is on, filled in by `Input::locate` for line based formats
+    pub task: Option<String>, // task name, if the corpus has them
+    pub offset: Option<usize>, // character offset of the problem, if it could be pinned down
+    pub message: String,
+    pub program: String,
+}
+
+impl ProgramError {
+    /// up to `context` characters either side of the offending character, with a caret under it
+    pub fn excerpt(&self, context: usize) -> Option<String> {
+        let offset = self.offset?;
+        let chars: Vec<char> = self.program.chars().collect();
+        let start = offset.saturating_sub(context);
+        let end = (offset + context + 1).min(chars.len());
+        let prefix = if start > 0 { "..." } else { "" };
+        let suffix = if end < chars.len() { "..." } else { "" };
+        let window: String = chars[start..end].iter().collect();
+        let caret_col = prefix.len() + offset - start;
+        Some(format!("{prefix}{window}{suffix}\n{}^", " ".repeat(caret_col)))
+    }
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "program {}", self.index)?;
+        if let Some(line) = self.line {
+            write!(f, " on line {line}")?;
+        }
+        if let Some(task) = &self.task {
+            write!(f, " (task {task:?})")?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at char {offset}")?;
+        }
+        write!(f, ": {}", self.message)?;
+        match self.excerpt(30) {
+            Some(excerpt) => excerpt.lines().try_for_each(|line| write!(f, "\n    {line}")),
+            None => write!(f, "\n    {}", self.program),
+        }
+    }
+}
+
+/// Parse every program, collecting a `ProgramError` for each one that fails instead of stopping at the first.
+/// Returns the programs that parsed along with their index in `programs`.
+pub fn parse_programs(programs: &[String], tasks: Option<&[String]>) -> (Vec<(usize, ExprOwned)>, Vec<ProgramError>) {
+    let mut parsed = vec![];
+    let mut errors = vec![];
+    for (i,p) in programs.iter().enumerate() {
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        match set.parse_extend(p) {
+            Ok(idx) => parsed.push((i, ExprOwned::new(set, idx))),
+            Err(e) => {
+                // the parser doesn't say where it failed, so we look for the problem ourselves
+                let (offset, message) = match locate_syntax_error(p) {
+                    Some((offset, message)) => (Some(offset), message),
+                    None => (None, e),
+                };
+                errors.push(ProgramError { index: i, line: None, task: tasks.map(|tasks| tasks[i].clone()), offset, message, program: p.clone() });
+            }
+        }
+    }
+    (parsed, errors)
+}
+
+/// whether the program parser reads `tok` as a lambda: `lam`, `lambda`, `lam_*` or `lambda_*`
+pub fn is_lambda_token(tok: &str) -> bool {
+    tok == "lam" || tok == "lambda" || tok.starts_with("lam_") || tok.starts_with("lambda_")
+}
+
+/// character offset and description of the first syntax problem in `program` that we know how to spot:
+/// unbalanced parens, empty applications, lambdas without a body, and anything after the end of the program
+fn locate_syntax_error(program: &str) -> Option<(usize, String)> {
+    // (char offset, token) pairs where parens are their own tokens
+    let mut tokens: Vec<(usize, String)> = vec![];
+    for (i,c) in program.chars().enumerate() {
+        if c == '(' || c == ')' {
+            tokens.push((i, c.to_string()));
+        } else if c.is_whitespace() {
+            continue
+        } else if matches!(tokens.last(), Some((j, tok)) if tok != "(" && tok != ")" && j + tok.chars().count() == i) {
+            tokens.last_mut().unwrap().1.push(c);
+        } else {
+            tokens.push((i, c.to_string()));
+        }
+    }
+    if tokens.is_empty() {
+        return Some((0, "empty program".to_string()))
+    }
+
+    let mut open: Vec<usize> = vec![]; // offsets of the parens that haven't been closed yet
+    for (k, (offset, tok)) in tokens.iter().enumerate() {
+        match tok.as_str() {
+            "(" => {
+                match tokens.get(k+1).map(|(_, tok)| tok.as_str()) {
+                    Some(")") => return Some((*offset, "empty application ()".to_string())),
+                    Some(next) if is_lambda_token(next) && tokens.get(k+2).map_or(")", |(_, tok)| tok.as_str()) == ")" =>
+                        return Some((tokens[k+1].0, format!("{next} without a body"))),
+                    _ => {},
+                }
+                open.push(*offset);
+            }
+            ")" => if open.pop().is_none() {
+                return Some((*offset, "unmatched )".to_string()))
+            },
+            _ => if is_lambda_token(tok) && (k == 0 || tokens[k-1].1 != "(") {
+                return Some((*offset, format!("{tok} outside of parens")))
+            },
+        }
+        if open.is_empty() && k + 1 < tokens.len() {
+            let message = if tokens[k+1].1 == ")" { "unmatched )" } else { "unexpected tokens after the end of the program" };
+            return Some((tokens[k+1].0, message.to_string()))
+        }
+    }
+    open.last().map(|offset| (*offset, "unclosed (".to_string()))
+}
+
+/// The corpus properties that `validate` checks
+#[derive(Parser, Debug, Serialize, Clone)]
+pub struct ValidateConfig {
+    /// Prefix that compression will name new abstractions with, so primitives like fn_3 would collide with them
+    #[clap(long, default_value = "fn_")]
+    pub abstraction_prefix: String,
+
+    /// The tags that will be passed to --fused-lambda-tags in `compress`. If set, untagged lambdas are reported
+    #[clap(long, value_parser = clap::value_parser!(FusedLambdaTags), default_value="")]
+    pub fused_lambda_tags: FusedLambdaTags,
+}
+
+impl Default for ValidateConfig {
+    fn default() -> Self {
+        Self::parse_from("validate".split_whitespace())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// a variable that isn't bound by any lambda in the program
+    FreeVariable,
+    /// a lambda applied to an argument, which `--eta-long` can't handle
+    NotBetaNormal,
+    /// a closed term that appears both in function position and as an argument or lambda body somewhere in the
+    /// corpus, which `--eta-long` assumes never happens
+    NotEtaLong,
+    /// a variable whose tag isn't the tag of the lambda that binds it, or an untagged lambda when fused lambda tags are used
+    TagMismatch,
+    /// a primitive named like an abstraction that compression would create, e.g. fn_3
+    PrefixCollision,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub subterm: String, // the offending subterm
+    pub message: String,
+}
+
+/// All the violations found in one program
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramViolations {
+    pub index: usize,
+    pub task: Option<String>,
+    pub program: String,
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ProgramViolations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "program {}", self.index)?;
+        if let Some(task) = &self.task {
+            write!(f, " (task {task:?})")?;
+        }
+        write!(f, ": {}", self.program)?;
+        self.violations.iter().try_for_each(|v| write!(f, "\n    {:?}: {}", v.kind, v.message))
+    }
+}
+
+/// Check a corpus for the properties that compression options rely on: no free variables (which break the utility
+/// calculation), beta-normal and eta-long form (assumed by `--eta-long`), lambda and variable tags that agree (assumed by
+/// `--fused-lambda-tags`), and no primitives that collide with the names of new abstractions. Returns the programs that
+/// have violations, in order.
+pub fn validate(programs: &[ExprOwned], tasks: Option<&[String]>, cfg: &ValidateConfig) -> Vec<ProgramViolations> {
+    // structurally hashed so the same subterm gets the same idx in every program, as in compression
+    let mut set = ExprSet::empty(Order::ChildFirst, false, true);
+    let roots: Vec<Idx> = programs.iter().map(|p| p.immut().copy_rec(&mut set)).collect();
+    let mut analyzed_free_vars = AnalyzedExpr::new(FreeVarAnalysis);
+    analyzed_free_vars.analyze(&set);
+
+    // terms in function position (to the left of an app), with the first program they appear there in
+    let mut func_position: FxHashMap<Idx, usize> = FxHashMap::default();
+    for (i, root) in roots.iter().enumerate() {
+        let mut stack = vec![*root];
+        while let Some(node) = stack.pop() {
+            if let Node::App(f, _) = &set[node] {
+                func_position.entry(*f).or_insert(i);
+            }
+            stack.extend(set[node].children());
+        }
+    }
+
+    let checker = Checker { set: &set, analyzed_free_vars: &analyzed_free_vars, func_position: &func_position, cfg };
+    roots.iter().enumerate().filter_map(|(i, root)| {
+        let mut violations: Vec<Violation> = vec![];
+        checker.check(*root, &mut vec![], &mut violations);
+        // the same subterm can show up many times in a program, once is enough
+        let mut seen: FxHashSet<(ViolationKind, String)> = FxHashSet::default();
+        violations.retain(|v| seen.insert((v.kind, v.message.clone())));
+        if violations.is_empty() {
+            return None
+        }
+        Some(ProgramViolations { index: i, task: tasks.map(|tasks| tasks[i].clone()), program: programs[i].to_string(), violations })
+    }).collect()
+}
+
+struct Checker<'a> {
+    set: &'a ExprSet,
+    analyzed_free_vars: &'a AnalyzedExpr<FreeVarAnalysis>,
+    func_position: &'a FxHashMap<Idx, usize>,
+    cfg: &'a ValidateConfig,
+}
+
+impl Checker<'_> {
+    /// `binders` are the tags of the lambdas above `node`, innermost last
+    fn check(&self, node: Idx, binders: &mut Vec<Tag>, violations: &mut Vec<Violation>) {
+        let mut push = |kind: ViolationKind, subterm: Idx, message: String| violations.push(Violation { kind, subterm: self.set.get(subterm).to_string(), message });
+        match &self.set[node] {
+            Node::Prim(p) => {
+                let p = p.to_string();
+                if let Some(suffix) = p.strip_prefix(self.cfg.abstraction_prefix.as_str()) {
+                    if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) {
+                        push(ViolationKind::PrefixCollision, node, format!("primitive {p} collides with the names of new abstractions (prefix {:?})", self.cfg.abstraction_prefix));
+                    }
+                }
+            }
+            Node::Var(i, tag) => {
+                match binders.len().checked_sub(*i as usize + 1).map(|j| binders[j]) {
+                    None => push(ViolationKind::FreeVariable, node, format!("{} is free", self.set.get(node))),
+                    Some(binder_tag) => if *tag != -1 && *tag != binder_tag {
+                        push(ViolationKind::TagMismatch, node, format!("{} is bound by a lambda tagged {binder_tag}", self.set.get(node)));
+                    }
+                }
+            }
+            Node::IVar(_) => {}
+            Node::App(f, x) => {
+                if let Node::Lam(_, _) = &self.set[*f] {
+                    push(ViolationKind::NotBetaNormal, node, format!("lambda applied to an argument in {}", self.set.get(node)));
+                }
+                self.check_not_function(*x, "an argument", &mut push);
+                self.check(*f, binders, violations);
+                self.check(*x, binders, violations);
+            }
+            Node::Lam(b, tag) => {
+                if *tag == -1 && self.cfg.fused_lambda_tags.tags().is_some() {
+                    push(ViolationKind::TagMismatch, node, "untagged lambda but fused lambda tags are in use".to_string());
+                }
+                self.check_not_function(*b, "a lambda body", &mut push);
+                binders.push(*tag);
+                self.check(*b, binders, violations);
+                binders.pop();
+            }
+        }
+    }
+
+    /// a closed term that's in function position somewhere can't be an argument or lambda body in eta-long form
+    fn check_not_function(&self, node: Idx, position: &str, push: &mut impl FnMut(ViolationKind, Idx, String)) {
+        if !self.analyzed_free_vars[node].is_empty() {
+            // eg $0 can validly appear to either the left or right of an app
+            return
+        }
+        if let Some(j) = self.func_position.get(&node) {
+            push(ViolationKind::NotEtaLong, node, format!("{} is {position} here but is applied to arguments in program {j}", self.set.get(node)));
+        }
+    }
+}
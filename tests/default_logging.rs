@@ -0,0 +1,15 @@
+//! Compression falls back to the console logger when no logger was installed. The logger is global to the process, so
+//! this gets a test binary of its own too, where nothing calls `init_logging` first
+
+use stitch_core::*;
+use clap::Parser;
+
+#[test]
+fn console_logging_by_default() {
+    assert_eq!(log::max_level(), log::LevelFilter::Off);
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a1".split_whitespace());
+    multistep_compression(&["(f a)".to_string(), "(f b)".to_string()], None, None, None, None, None, None, &cfg).unwrap();
+    assert_eq!(log::max_level(), LogConfig::default().log_level);
+    // the console logger is installed now, so it's too late for another one
+    assert!(init_logging(&LogConfig::default()).is_err());
+}
@@ -22,33 +22,63 @@ fn run_compression(inputs: &Input, cfg: &MultistepCompressionConfig) -> Value {
     multistep_compression(
         &inputs.train_programs,
         inputs.tasks.clone(),
-        None,
+        inputs.weights.clone(),
         inputs.name_mapping.clone(),
+        inputs.inventions.clone(),
+        None,
         None,
         cfg,
-        ).1
+        ).unwrap().1
 }
 
 fn compare_out_jsons(file: &str, expected_out_file: &str, args: &str, input_format: InputFormat) {
     let input = input_format.load_programs_and_tasks(std::path::Path::new(file)).unwrap();
 
-    let mut cfg = MultistepCompressionConfig::parse_from(format!("compress {args}").split_whitespace());
+    let mut cfg = MultistepCompressionConfig::from_args(format!("compress {args}").split_whitespace()).unwrap();
 
     cfg.previous_abstractions = input.name_mapping.clone().unwrap_or_default().len();
 
     let output = run_compression(&input, &cfg);
 
+    compare_with_expected(&output, expected_out_file);
+}
+
+/// run one iteration, then resume from its output json and check that we end up with the same result as an uninterrupted run
+fn compare_resumed_out_jsons(file: &str, expected_out_file: &str, args: &str, input_format: InputFormat) {
+    let input = input_format.load_programs_and_tasks(std::path::Path::new(file)).unwrap();
+
+    let mut cfg = MultistepCompressionConfig::parse_from(format!("compress {args}").split_whitespace());
+    cfg.previous_abstractions = input.name_mapping.clone().unwrap_or_default().len();
+
+    let mut first_step_cfg = cfg.clone();
+    first_step_cfg.iterations = 1;
+    let checkpoint = Checkpoint::from_json(&run_compression(&input, &first_step_cfg)).unwrap();
+
+    let output = multistep_compression_resume(
+        &checkpoint,
+        input.tasks.clone(),
+        input.weights.clone(),
+        input.name_mapping.clone(),
+        input.inventions.clone(),
+        None,
+        &cfg,
+        ).unwrap().1;
+
+    compare_with_expected(&output, expected_out_file);
+}
+
+fn compare_with_expected(output: &Value, expected_out_file: &str) {
     println!("{}", serde_json::to_string(&output).unwrap());
 
     let expected_output: Value = serde_json::from_str(&std::fs::read_to_string(std::path::Path::new(expected_out_file)).unwrap()).unwrap();
 
-    check_eq(&output["original"], &expected_output["original"], vec!["original".into()], &output, expected_out_file);
-    check_eq(&output["original_cost"], &expected_output["original_cost"], vec!["original_cost".into()], &output, expected_out_file);
-    check_eq(&output["final_cost"], &expected_output["final_cost"], vec!["final_cost".into()], &output, expected_out_file);
-    check_eq(&output["compression_ratio"], &expected_output["compression_ratio"], vec!["compression_ratio".into()], &output, expected_out_file);
-    check_eq(&output["num_abstractions"], &expected_output["num_abstractions"], vec!["num_abstractions".into()], &output, expected_out_file);
-    check_eq(&output["abstractions"], &expected_output["abstractions"], vec!["abstractions".into()], &output, expected_out_file);
-    check_eq(&output["rewritten"], &expected_output["rewritten"], vec!["rewritten".into()], &output, expected_out_file);
+    check_eq(&output["original"], &expected_output["original"], vec!["original".into()], output, expected_out_file);
+    check_eq(&output["original_cost"], &expected_output["original_cost"], vec!["original_cost".into()], output, expected_out_file);
+    check_eq(&output["final_cost"], &expected_output["final_cost"], vec!["final_cost".into()], output, expected_out_file);
+    check_eq(&output["compression_ratio"], &expected_output["compression_ratio"], vec!["compression_ratio".into()], output, expected_out_file);
+    check_eq(&output["num_abstractions"], &expected_output["num_abstractions"], vec!["num_abstractions".into()], output, expected_out_file);
+    check_eq(&output["abstractions"], &expected_output["abstractions"], vec!["abstractions".into()], output, expected_out_file);
+    check_eq(&output["rewritten"], &expected_output["rewritten"], vec!["rewritten".into()], output, expected_out_file);
 
 }
 
@@ -146,6 +176,630 @@ fn nuts_bolts_a3_i10() {
     compare_out_jsons("data/cogsci/nuts-bolts.json", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::ProgramsList);
 }
 #[test]
+fn nuts_bolts_sexp_a3_i10() {
+    compare_out_jsons("data/cogsci/nuts-bolts.sexp", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::Sexp);
+}
+#[test]
+fn nuts_bolts_json_objects_a3_i10() {
+    compare_out_jsons("data/cogsci/nuts-bolts-records.json", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::JsonObjects);
+}
+#[test]
+fn nuts_bolts_jsonl_a3_i10() {
+    compare_out_jsons("data/cogsci/nuts-bolts.jsonl", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::Jsonl);
+}
+#[test]
+fn json_objects_records_passthrough() {
+    let input = InputFormat::JsonObjects.load_programs_and_tasks(std::path::Path::new("data/cogsci/nuts-bolts-records.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i3 -a3".split_whitespace());
+    let rewritten: Vec<String> = serde_json::from_value(run_compression(&input, &cfg)["rewritten"].clone()).unwrap();
+    let records = input.rewritten_records(&rewritten).unwrap();
+    for (i,(record, original)) in records.iter().zip(input.records.as_ref().unwrap()).enumerate() {
+        assert_eq!(record["program"].as_str().unwrap(), rewritten[i]);
+        assert_eq!(record["id"], original["id"]);
+        assert_eq!(record["meta"], original["meta"]);
+    }
+}
+#[test]
+fn records_reject_reordering() {
+    let input = InputFormat::JsonObjects.load_programs_and_tasks(std::path::Path::new("data/cogsci/nuts-bolts-records.json")).unwrap();
+    for args in ["--shuffle", "--truncate 10"] {
+        let cfg = MultistepCompressionConfig::parse_from(format!("compress -i1 -a2 {args}").split_whitespace());
+        assert!(matches!(input.check_records_line_up(&cfg, None), Err(StitchError::InvalidInput(message)) if message.contains("line up")));
+        // programs without records can be reordered
+        let programs_only = Input { records: None, ..input.clone() };
+        assert!(programs_only.check_records_line_up(&cfg, None).is_ok());
+    }
+    let cfg = MultistepCompressionConfig::parse_from("compress -i2 -a2".split_whitespace());
+    assert!(input.check_records_line_up(&cfg, None).is_ok());
+    // nor can a run that was shuffled be resumed with them
+    let shuffled_cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --shuffle".split_whitespace());
+    let checkpoint = Checkpoint::from_json(&run_compression(&input, &shuffled_cfg)).unwrap();
+    assert!(matches!(input.check_records_line_up(&cfg, Some(&checkpoint)), Err(StitchError::InvalidInput(_))));
+}
+#[test]
+fn untasked_records_keep_their_own_tasks() {
+    let path = std::env::temp_dir().join(format!("stitch_untasked_{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{"program": "(f a)"}, {"program": "(f b)", "task": "untasked_0"}, {"program": "(g a)", "task": "1"}, {"program": "(g b)"}]"#).unwrap();
+    let input = InputFormat::JsonObjects.load_programs_and_tasks(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let tasks = input.tasks.unwrap();
+    assert_eq!(&tasks[1..3], &["untasked_0", "1"]);
+    // the made up tasks of the first and last record are neither merged with each other nor with the real ones
+    assert_eq!(tasks.iter().collect::<std::collections::HashSet<_>>().len(), 4);
+}
+#[test]
+fn nuts_bolts_json_tree_a3_i10() {
+    compare_out_jsons("data/cogsci/nuts-bolts-tree.json", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::JsonTree);
+}
+#[test]
+fn json_tree_round_trip() {
+    let path = std::path::Path::new("data/cogsci/nuts-bolts-tree.json");
+    let trees: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let tree_cfg = TreeConfig { tree_arity_tags: true, tree_arity_sep: "/".to_string() };
+    let input = InputFormat::JsonTree.load_programs_and_tasks_cfg(path, &tree_cfg).unwrap();
+    let enc = input.tree.clone().unwrap();
+    assert_eq!(enc.shape, TreeShape::Arrays);
+    assert!(input.train_programs[0].starts_with("(C/2 (C/2 "));
+    for (program, tree) in input.train_programs.iter().zip(trees.iter()) {
+        assert_eq!(&tree_of_program(program, &enc).unwrap(), tree);
+    }
+
+    // node types that would otherwise be read as lambdas, variables, or several tokens
+    let tree = serde_json::json!({"type": "lambda", "children": [
+        {"type": "$x", "children": []},
+        {"type": "a (b) 100%", "children": []},
+        {"type": "", "children": []},
+    ]});
+    let program = program_of_tree(&tree, &tree_cfg).unwrap();
+    assert_eq!(program, "(%6Cambda/3 %24x/0 a%20%28b%29%20100%25/0 %/0)");
+    let enc = TreeEncoding { shape: TreeShape::Objects, cfg: tree_cfg };
+    assert_eq!(tree_of_program(&program, &enc).unwrap(), tree);
+
+    // every kind of lambda the parser accepts is escaped, including tagged ones
+    let untagged_cfg = TreeConfig::default();
+    for ty in ["lam", "lambda", "lam_2", "lambda_2"] {
+        let tree = serde_json::json!({"type": "f", "children": [{"type": ty, "children": []}, {"type": "x", "children": []}]});
+        let program = program_of_tree(&tree, &untagged_cfg).unwrap();
+        assert_eq!(program, format!("(f %6C{} x)", &ty[1..]));
+        let (parsed, invalid) = parse_programs(std::slice::from_ref(&program), None);
+        assert!(invalid.is_empty());
+        assert_eq!(parsed[0].1.to_string(), program);
+        let enc = TreeEncoding { shape: TreeShape::Objects, cfg: untagged_cfg.clone() };
+        assert_eq!(tree_of_program(&program, &enc).unwrap(), tree);
+    }
+}
+#[test]
+fn logo_iteration_1_dreamcoder_checkpoint() {
+    let path = std::path::Path::new("data/dc/logo_iteration_1.json");
+    let dc_json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let input = InputFormat::Dreamcoder.load_programs_and_tasks(path).unwrap();
+    let mut cfg = MultistepCompressionConfig::parse_from("compress -i3 -a3 --rewritten-dreamcoder".split_whitespace());
+    cfg.previous_abstractions = input.name_mapping.clone().unwrap_or_default().len();
+    let (step_results, json_res) = multistep_compression(&input.train_programs, input.tasks.clone(), input.weights.clone(), input.name_mapping.clone(), input.inventions.clone(), None, None, &cfg).unwrap();
+
+    let inventions: Vec<String> = step_results.iter().map(|res| res.dc_inv_str.clone()).collect();
+    let rewritten_dreamcoder = step_results.last().unwrap().rewritten_dreamcoder.clone().unwrap();
+    let dc_checkpoint = dreamcoder_checkpoint(&dc_json, &inventions, &rewritten_dreamcoder).unwrap();
+
+    // original productions come first, then the new inventions
+    let productions = dc_checkpoint["DSL"]["productions"].as_array().unwrap();
+    let original_productions = dc_json["DSL"]["productions"].as_array().unwrap();
+    assert_eq!(productions.len(), original_productions.len() + 3);
+    assert_eq!(&productions[..original_productions.len()], &original_productions[..]);
+    for (production, abstraction) in productions[original_productions.len()..].iter().zip(json_res["abstractions"].as_array().unwrap()) {
+        assert_eq!(production["expression"], abstraction["dreamcoder"]);
+    }
+    assert_eq!(dc_checkpoint["DSL"]["logVariable"], dc_json["DSL"]["logVariable"]);
+
+    // same frontiers in the same order with only the programs changed
+    let frontiers = dc_checkpoint["frontiers"].as_array().unwrap();
+    let mut rewritten = rewritten_dreamcoder.iter();
+    for (frontier, original) in frontiers.iter().zip(dc_json["frontiers"].as_array().unwrap()) {
+        assert_eq!(frontier["task"], original["task"]);
+        assert_eq!(frontier["request"], original["request"]);
+        for (p, original_p) in frontier["programs"].as_array().unwrap().iter().zip(original["programs"].as_array().unwrap()) {
+            assert_eq!(p["program"].as_str().unwrap(), rewritten.next().unwrap());
+            assert_eq!(p["logLikelihood"], original_p["logLikelihood"]);
+        }
+    }
+    assert!(rewritten.next().is_none());
+}
+#[test]
+fn rewrite_logo_iteration_1_dreamcoder() {
+    let path = std::path::Path::new("data/dc/logo_iteration_1.json");
+    let dc_json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let input = InputFormat::Dreamcoder.load_programs_and_tasks(path).unwrap();
+    let library: Value = serde_json::from_str(&std::fs::read_to_string("data/expected_outputs/logo_iteration_1-a3-i10.json").unwrap()).unwrap();
+    let abstractions = library["abstractions"].as_array().unwrap();
+    let invs: Vec<Invention> = abstractions.iter().map(|abstraction| {
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(abstraction["body"].as_str().unwrap()).unwrap();
+        Invention::new(ExprOwned::new(set, idx), abstraction["arity"].as_u64().unwrap() as usize, abstraction["name"].as_str().unwrap())
+    }).collect();
+    let inv_dreamcoder: Vec<String> = abstractions.iter().map(|abstraction| abstraction["dreamcoder"].as_str().unwrap().to_string()).collect();
+
+    let cfg = MultistepCompressionConfig::new();
+    let rewritten = rewrite_with_inventions(&input.train_programs, &invs, &cfg).unwrap().0;
+    let output = rewritten_dreamcoder_json(&dc_json, &input, &rewritten, &invs, &inv_dreamcoder, true).unwrap();
+
+    let expected_out_file = "data/expected_outputs/logo_iteration_1-rewrite.json";
+    let expected_output: Value = serde_json::from_str(&std::fs::read_to_string(expected_out_file).unwrap()).unwrap();
+    check_eq(&output, &expected_output, vec![], &output, expected_out_file);
+    // key order is part of the structure too
+    assert_eq!(output.as_object().unwrap().keys().collect::<Vec<_>>(), dc_json.as_object().unwrap().keys().collect::<Vec<_>>());
+}
+#[test]
+fn dreamcoder_inventions_round_trip() {
+    let path = std::path::Path::new("data/dc/logo_iteration_1.json");
+    let name_mapping = InputFormat::Dreamcoder.load_programs_and_tasks(path).unwrap().name_mapping.unwrap();
+    let dc_json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let mut dc_invs: Vec<String> = dc_json["DSL"]["productions"].as_array().unwrap().iter()
+        .map(|prod| prod["expression"].as_str().unwrap().to_string())
+        .filter(|s| s.starts_with('#'))
+        .collect();
+    dc_invs.sort_by_key(|s| s.len());
+    let inventions = DreamcoderInventions::from_productions(&dc_invs).unwrap().inventions;
+    assert_eq!(inventions.len(), name_mapping.len());
+    assert!(inventions.iter().any(|inv| inv.arity > 0 && inv.body.to_string().contains("dreamcoder_abstraction_")));
+    for (i, (inv, (name, dc_inv))) in inventions.iter().zip(name_mapping.iter()).enumerate() {
+        assert_eq!(&inv.name, name);
+        assert_eq!(&dc_inv_str(inv, &name_mapping[..i]), dc_inv);
+    }
+
+    // prefixes of other names and repeated uses are left alone or all replaced
+    let dc = DreamcoderInventions::from_productions(&["#(lambda (f $0))".to_string(), "#(lambda (#(lambda (f $0)) (g $0)))".to_string()]).unwrap();
+    let program = dc.replace_inventions("(h #(lambda (#(lambda (f $0)) (g $0))) #(lambda (f $0)) #(lambda (f $0)))").unwrap();
+    assert_eq!(program, "(h dreamcoder_abstraction_1 dreamcoder_abstraction_0 dreamcoder_abstraction_0)");
+    let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+    let idx = set.parse_extend(&program).unwrap();
+    assert_eq!(dreamcoder_string(set.get(idx), &dc.name_mapping), "(h #(lambda (#(lambda (f $0)) (g $0))) #(lambda (f $0)) #(lambda (f $0)))");
+    assert!(dc.replace_inventions("(h #(lambda (q $0)))").is_err());
+}
+#[test]
+fn dreamcoder_inventions_are_prior_abstractions() {
+    let path = std::path::Path::new("data/dc/logo_iteration_1.json");
+    let dc_json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let productions: Vec<&str> = dc_json["DSL"]["productions"].as_array().unwrap().iter().map(|prod| prod["expression"].as_str().unwrap()).collect();
+    let input = InputFormat::Dreamcoder.load_programs_and_tasks(path).unwrap();
+    let inventions = input.inventions.clone().unwrap();
+    assert_eq!(inventions.len(), productions.iter().filter(|prod| prod.starts_with('#')).count());
+    let mut cfg = MultistepCompressionConfig::parse_from("compress -i2 -a3 --silent".split_whitespace());
+    cfg.previous_abstractions = inventions.len();
+    let output = run_compression(&input, &cfg);
+
+    let prior = output["prior_abstractions"].as_array().unwrap();
+    assert_eq!(prior.len(), inventions.len());
+    for (abstraction, inv) in prior.iter().zip(inventions.iter()) {
+        assert_eq!(abstraction["name"], inv.name.as_str());
+        assert_eq!(abstraction["arity"], inv.arity);
+        assert_eq!(abstraction["body"], inv.body.to_string());
+        assert!(productions.contains(&abstraction["dreamcoder"].as_str().unwrap()));
+    }
+
+    // the call graph starts with them, so it shows how the learned abstractions and the DSL's build on each other
+    let graph = CallGraph::from_json(&output).unwrap();
+    assert_eq!(graph.nodes.len(), inventions.len() + 2);
+    assert!(graph.nodes.iter().zip(inventions.iter()).all(|(node, inv)| node.name == inv.name && node.arity == inv.arity));
+    assert!(graph.nodes[..inventions.len()].iter().any(|node| node.uses > 0));
+    assert!(graph.nodes[..inventions.len()].iter().any(|node| node.depth > 0));
+}
+#[test]
+fn nuts_bolts_report() {
+    let input = InputFormat::ProgramsList.load_programs_and_tasks(std::path::Path::new("data/cogsci/nuts-bolts.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i10 -a3".split_whitespace());
+    let output = run_compression(&input, &cfg);
+    let abstractions = output["abstractions"].as_array().unwrap();
+    assert_eq!(abstractions[0]["use_args"].as_array().unwrap().len(), abstractions[0]["uses"].as_array().unwrap().len());
+
+    let md = report(&output, ReportFormat::Markdown, &ReportConfig::default()).unwrap();
+    let html = report(&output, ReportFormat::Html, &ReportConfig::default()).unwrap();
+    for abstraction in abstractions {
+        let name = abstraction["name"].as_str().unwrap();
+        assert!(md.contains(&format!("### {name}")));
+        assert!(md.contains(&format!("| {:.2}x |", abstraction["cumulative_compression_ratio"].as_f64().unwrap())));
+        assert!(html.contains(&format!("<h3 id=\"{name}\">")));
+    }
+    // uses are ranked by how often they appear in the corpus each abstraction was found in, which for the later ones
+    // contains the earlier ones
+    for abstraction in abstractions {
+        let uses = top_uses(abstraction);
+        assert_eq!(uses.iter().map(|u| u.count).sum::<u64>(), abstraction["num_uses"].as_u64().unwrap());
+        assert!(uses.windows(2).all(|w| w[0].count >= w[1].count));
+        assert!(uses.iter().all(|u| u.count > 0));
+    }
+    assert!(abstractions.iter().flat_map(top_uses).any(|u| u.expr.contains("fn_")));
+    // the most common use of fn_0 and its argument bindings
+    let top = &top_uses(&abstractions[0])[0];
+    assert_eq!((top.count, top.args.clone()), (106, vec!["4".to_string(), "6".to_string()]));
+    assert!(html.contains("<polyline"));
+}
+#[test]
+fn nuts_bolts_callgraph() {
+    let output: Value = serde_json::from_str(&std::fs::read_to_string("data/expected_outputs/nuts-bolts-a3-i10.json").unwrap()).unwrap();
+    let graph = CallGraph::from_json(&output).unwrap();
+    let json = graph.to_json();
+    assert_eq!(json["fn_0"]["depth"], 0);
+    assert_eq!(json["fn_0"]["uses"], 54);
+    assert_eq!(json["fn_1"]["uses"], 0);
+    assert_eq!(json["fn_8"]["calls"], serde_json::json!({"fn_0": 1, "fn_2": 1}));
+    assert_eq!(json["fn_8"]["depth"], 1);
+    assert_eq!(json["fn_9"]["calls"], serde_json::json!({"fn_4": 1}));
+    assert_eq!(json["fn_9"]["depth"], 2);
+    let dot = graph.to_dot();
+    assert!(dot.contains("\"fn_9\" -> \"fn_4\" [label=\"1\"];"));
+    assert!(dot.contains("{ rank=same; \"fn_9\"; }"));
+}
+#[test]
+fn nuts_bolts_iteration_csv() {
+    let input = InputFormat::ProgramsList.load_programs_and_tasks(std::path::Path::new("data/cogsci/nuts-bolts.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i3 -a3".split_whitespace());
+    let (step_results, output) = multistep_compression(&input.train_programs, None, None, None, None, None, None, &cfg).unwrap();
+    let csv = csv_of_step_results(&step_results, 0);
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0][..12], ["iteration", "name", "arity", "utility", "final_cost", "compression_ratio", "cumulative_compression_ratio", "num_uses", "num_tasks", "prep_millis", "search_millis", "worklist_steps"]);
+    assert!(rows[0].contains(&"upper_bound_fired"));
+    for (i, (row, abstraction)) in rows[1..].iter().zip(output["abstractions"].as_array().unwrap()).enumerate() {
+        assert_eq!(row.len(), rows[0].len());
+        assert_eq!(row[0], i.to_string());
+        assert_eq!(row[1], abstraction["name"].as_str().unwrap());
+        assert_eq!(row[3], abstraction["utility"].to_string());
+        assert_eq!(row[7], abstraction["num_uses"].to_string());
+        // every program is its own task here, and some use an abstraction more than once
+        let num_tasks: i64 = row[8].parse().unwrap();
+        assert!(num_tasks > 0 && num_tasks <= abstraction["num_uses"].as_i64().unwrap());
+        assert!(row[11].parse::<usize>().unwrap() > 0);
+        // the same stats and timings are in the output json
+        assert_eq!(row[11], abstraction["stats"]["worklist_steps"].to_string());
+        assert_eq!(row[rows[0].iter().position(|col| *col == "upper_bound_fired").unwrap()], abstraction["stats"]["upper_bound_fired"].to_string());
+        assert_eq!(row[9], abstraction["timings"]["prep_millis"].to_string());
+        let timings = abstraction["timings"].as_object().unwrap();
+        assert_eq!(timings.keys().collect::<Vec<_>>(), ["prep_millis", "zippers_millis", "analyses_millis", "arity_zero_millis", "search_millis", "rewrite_millis"]);
+        assert!(timings["zippers_millis"].as_u64().unwrap() + timings["analyses_millis"].as_u64().unwrap() + timings["arity_zero_millis"].as_u64().unwrap() <= timings["prep_millis"].as_u64().unwrap());
+        let memory = &abstraction["memory"];
+        // peak memory is only measured on Linux
+        if cfg!(target_os = "linux") {
+            assert!(memory["peak_rss_kb"].as_u64().unwrap() > 0);
+        } else {
+            assert!(memory["peak_rss_kb"].is_null());
+        }
+        assert!(memory["zip_of_zid"].as_u64().unwrap() > 0);
+        assert!(memory["arg_of_zid_node"].as_u64().unwrap() > 0);
+        assert!(memory["worklist_high_water"].as_u64().unwrap() > 0);
+        assert!(memory["expr_set"].as_u64().unwrap() > 0);
+    }
+}
+#[test]
+fn invalid_inputs_are_errors() {
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2".split_whitespace());
+    let programs = vec!["(f a b)".to_string(), "(f a (g b)".to_string()];
+    assert!(matches!(multistep_compression(&programs, None, None, None, None, None, None, &cfg), Err(StitchError::InvalidPrograms(_))));
+    let programs = vec!["(f a b)".to_string(), "(f a c)".to_string()];
+    assert!(matches!(multistep_compression(&programs, Some(vec!["t".to_string()]), None, None, None, None, None, &cfg), Err(StitchError::InvalidInput(_))));
+    assert!(matches!(InputFormat::ProgramsList.load_programs_and_tasks(Path::new("data/does-not-exist.json")), Err(StitchError::Io { .. })));
+    assert!(matches!(InputFormat::Dreamcoder.load_programs_and_tasks(Path::new("data/cogsci/nuts-bolts.json")), Err(StitchError::Format { .. })));
+    // eta long compression needs a beta-normal corpus
+    let eta_long_cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --eta-long --utility-by-rewrite".split_whitespace());
+    let programs = vec!["((lam (f $0 a)) b)".to_string(), "((lam (f $0 a)) c)".to_string()];
+    assert!(matches!(multistep_compression(&programs, None, None, None, None, None, None, &eta_long_cfg), Err(StitchError::InvalidInput(message)) if message.contains("beta-normal")));
+}
+#[test]
+fn invalid_programs_are_all_reported() {
+    let programs: Vec<String> = ["(f a b)", "(f a (g b)", "(f a b)", "(f (lam) b)", "(f b a))", "(f a b)"].iter().map(|p| p.to_string()).collect();
+    let tasks: Vec<String> = (0..programs.len()).map(|i| format!("task_{i}")).collect();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --silent".split_whitespace());
+    let errors = match multistep_compression(&programs, Some(tasks.clone()), None, None, None, None, None, &cfg) {
+        Err(StitchError::InvalidPrograms(errors)) => errors,
+        res => panic!("expected invalid programs, got {:?}", res.map(|(_, json)| json)),
+    };
+    assert_eq!(errors.iter().map(|e| (e.index, e.offset)).collect::<Vec<_>>(), vec![(1, Some(0)), (3, Some(4)), (4, Some(7))]);
+    assert_eq!(errors[0].task.as_deref(), Some("task_1"));
+    assert_eq!(errors[1].excerpt(30).unwrap(), "(f (lam) b)\n    ^");
+    assert!(errors[2].to_string().starts_with("program 4 (task \"task_4\") at char 7: unmatched )"));
+
+    // the rest can still be compressed
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --silent --skip-invalid".split_whitespace());
+    let (_, output) = multistep_compression(&programs, Some(tasks), None, None, None, None, None, &cfg).unwrap();
+    assert_eq!(output["original"].as_array().unwrap().len(), 3);
+    assert_eq!(output["invalid_programs"].as_array().unwrap().iter().map(|p| p["index"].as_u64().unwrap()).collect::<Vec<_>>(), vec![1, 3, 4]);
+}
+#[test]
+fn invalid_sexp_programs_report_their_line() {
+    let path = std::env::temp_dir().join(format!("stitch_invalid_{}.sexp", std::process::id()));
+    std::fs::write(&path, "; a comment\n(f a b)\n\n   \n; another comment\n(f a c) ; trailing comment\n(f (g a b)\n(f a d)\n").unwrap();
+    let input = InputFormat::Sexp.load_programs_and_tasks(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(input.lines, Some(vec![2, 6, 7, 8]));
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --silent".split_whitespace());
+    let err = multistep_compression(&input.train_programs, None, None, None, None, None, None, &cfg).map_err(|e| input.locate_error(e)).unwrap_err();
+    let errors = match &err {
+        StitchError::InvalidPrograms(errors) => errors,
+        e => panic!("expected invalid programs, got {}", e),
+    };
+    assert_eq!(errors.iter().map(|e| (e.index, e.line)).collect::<Vec<_>>(), vec![(2, Some(7))]);
+    assert!(err.to_string().contains("program 2 on line 7"));
+}
+
+#[test]
+fn sexp_comments_start_tokens() {
+    let path = std::env::temp_dir().join(format!("stitch_comments_{}.sexp", std::process::id()));
+    std::fs::write(&path, "  ; a comment\n(f a;b c);trailing\n(f \"x ; y\" \"\\\" ;\") ; trailing\n(f ;x\n").unwrap();
+    let input = InputFormat::Sexp.load_programs_and_tasks(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(input.train_programs, vec!["(f a;b c)", "(f \"x ; y\" \"\\\" ;\")", "(f"]);
+    assert_eq!(input.lines, Some(vec![2, 3, 4]));
+}
+#[test]
+fn validate_corpus() {
+    let programs: Vec<String> = [
+        "(g a b)",
+        "(f $0 b)",
+        "((lam (h $0)) a)",
+        "(k (g a))",
+        "(lam_1 (lam_2 (h $1_1 $0_1)))",
+        "(fn_3 (fn_x a))",
+    ].iter().map(|p| p.to_string()).collect();
+    let (parsed, invalid) = parse_programs(&programs, None);
+    assert!(invalid.is_empty());
+    let programs: Vec<ExprOwned> = parsed.into_iter().map(|(_, p)| p).collect();
+    let violations = validate(&programs, None, &ValidateConfig::default());
+    let kinds: Vec<(usize, Vec<ViolationKind>)> = violations.iter().map(|v| (v.index, v.violations.iter().map(|v| v.kind).collect())).collect();
+    assert_eq!(kinds, vec![
+        (1, vec![ViolationKind::FreeVariable]),
+        (2, vec![ViolationKind::NotBetaNormal]),
+        (3, vec![ViolationKind::NotEtaLong]),
+        (4, vec![ViolationKind::TagMismatch]),
+        (5, vec![ViolationKind::PrefixCollision]),
+    ]);
+    assert_eq!(violations[2].violations[0].subterm, "(g a)");
+    assert!(violations[2].violations[0].message.contains("program 0"));
+    assert_eq!(violations[3].violations[0].subterm, "$0_1");
+
+    // untagged lambdas only matter with fused lambda tags
+    let programs: Vec<ExprOwned> = parse_programs(&["(lam (h $0))".to_string()], None).0.into_iter().map(|(_, p)| p).collect();
+    assert!(validate(&programs, None, &ValidateConfig::default()).is_empty());
+    let cfg = ValidateConfig::parse_from("validate --fused-lambda-tags 1".split_whitespace());
+    assert_eq!(validate(&programs, None, &cfg)[0].violations[0].kind, ViolationKind::TagMismatch);
+    assert!(validate(&parse_programs(&serde_json::from_str::<Vec<String>>(&std::fs::read_to_string("data/cogsci/nuts-bolts.json").unwrap()).unwrap(), None).0.into_iter().map(|(_, p)| p).collect::<Vec<_>>(), None, &ValidateConfig::default()).is_empty());
+}
+#[test]
+fn corpus_stats() {
+    let programs: Vec<ExprOwned> = parse_programs(&["(f (g a) (g a))".to_string(), "(lam (f (g a) $0))".to_string(), "(h b)".to_string()], None).0.into_iter().map(|(_, p)| p).collect();
+    let tasks: Vec<String> = vec!["x".to_string(), "x".to_string(), "y".to_string()];
+    let stats = CorpusStats::new(&programs, Some(&tasks), &CostConfig::parse_from("stats".split_whitespace()).expr_cost(), 1);
+    assert_eq!(stats.num_tasks, 2);
+    assert_eq!(stats.programs_per_task, vec![(1, 1), (2, 1)]);
+    assert_eq!(stats.prim_frequencies[..3], [("a".to_string(), 3), ("g".to_string(), 3), ("f".to_string(), 2)]);
+    assert_eq!(stats.most_repeated.len(), 1);
+    assert_eq!((stats.most_repeated[0].expr.as_str(), stats.most_repeated[0].occurrences, stats.most_repeated[0].num_programs), ("(g a)", 3, 2));
+    assert_eq!(stats.lambda_depths, vec![(0, 2), (1, 1)]);
+    assert!(stats.distinct_subtrees < stats.num_nodes);
+}
+#[test]
+fn corpus_stats_of_empty_corpus() {
+    let stats = CorpusStats::new(&[], None, &CostConfig::parse_from("stats".split_whitespace()).expr_cost(), 1);
+    assert_eq!(stats.num_programs, 0);
+    for summary in [&stats.cost, &stats.length, &stats.depth] {
+        assert_eq!((summary.mean, summary.std, summary.min, summary.max), (0., 0., 0, 0));
+    }
+    // NaNs would come out as null in --json
+    assert_eq!(serde_json::to_value(&stats).unwrap()["cost"]["mean"], 0.0);
+}
+#[test]
+fn library_diff() {
+    use serde_json::json;
+    let old: Value = serde_json::from_str(&std::fs::read_to_string("data/expected_outputs/nuts-bolts-a3-i10.json").unwrap()).unwrap();
+    let cost_fn = CostConfig::parse_from("diff".split_whitespace()).expr_cost();
+
+    let same = LibraryDiff::new(&old, &old, &cost_fn).unwrap();
+    assert_eq!(same.kept.len(), old["num_abstractions"].as_u64().unwrap() as usize);
+    assert!(same.added.is_empty() && same.removed.is_empty());
+    assert!(same.kept.iter().all(|k| k.old.name == k.new.name && k.utility_delta == 0 && k.num_uses_delta == 0));
+    let programs = same.programs.unwrap();
+    assert_eq!((programs.num_improved, programs.num_worsened, programs.old_cost), (0, 0, programs.new_cost));
+
+    // rename fn_0 and swap its arguments (in its callers too), and drop the last abstraction
+    let mut new = old.clone();
+    let abstractions = new["abstractions"].as_array_mut().unwrap();
+    let last = abstractions.pop().unwrap();
+    for a in abstractions.iter_mut() {
+        let body = a["body"].as_str().unwrap().replace("(fn_0 4 #1)", "(fn_renamed #1 4)").replace("(fn_0 #0 6)", "(fn_renamed 6 #0)");
+        assert!(!body.contains("fn_0 "));
+        a["body"] = json!(body);
+    }
+    abstractions[0]["name"] = json!("fn_renamed");
+    abstractions[0]["body"] = json!(abstractions[0]["body"].as_str().unwrap().replace("#0", "#tmp").replace("#1", "#0").replace("#tmp", "#1"));
+    abstractions[0]["utility"] = json!(abstractions[0]["utility"].as_i64().unwrap() - 5);
+    new["original"] = json!([]);
+
+    let diff = LibraryDiff::new(&old, &new, &cost_fn).unwrap();
+    assert_eq!(diff.kept.len(), old["num_abstractions"].as_u64().unwrap() as usize - 1);
+    assert_eq!((diff.kept[0].old.name.as_str(), diff.kept[0].new.name.as_str(), diff.kept[0].utility_delta), ("fn_0", "fn_renamed", -5));
+    assert!(diff.added.is_empty());
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.removed[0].name, last["name"].as_str().unwrap());
+    // different programs so they aren't compared
+    assert!(diff.programs.is_none());
+}
+/// collects what the server writes so the tests can read the responses back
+#[derive(Clone, Default)]
+struct SharedBuf(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuf {
+    /// responses written so far, in order
+    fn all_responses(&self) -> Vec<Value> {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect()
+    }
+
+    /// responses written so far, by id
+    fn responses(&self) -> std::collections::HashMap<String, Value> {
+        self.all_responses().into_iter().map(|response| (response["id"].to_string(), response)).collect()
+    }
+}
+
+#[test]
+fn serve_requests() {
+    let out = SharedBuf::default();
+    let mut server = Server::new(None, TreeConfig::default(), out.clone());
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 0, "method": "stats"}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 1, "method": "load", "params": {"programs": ["(f (g a) b)", "(f (g c) b)", "(h (f (g a) b))", "(f"]}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 2, "method": "match", "params": {"pattern": "(f (g #0) b)"}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 3, "method": "stats", "params": {"top": 1}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 4, "method": "compress", "params": {"args": "-i1 -a1 --skip-invalid"}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 5, "method": "nonexistent"}"#);
+    server.handle_line("not json");
+    server.handle_line(r#"{"jsonrpc": "2.0", "method": "stats"}"#);
+    server.wait();
+
+    let responses = out.responses();
+    assert_eq!(responses.len(), 7); // the notification gets no response
+    assert_eq!(responses["0"]["error"]["code"], -32602);
+    assert_eq!(responses["1"]["result"]["invalid_programs"].as_array().unwrap().len(), 1);
+    let matches = responses["2"]["result"]["matches"].as_array().unwrap();
+    assert_eq!(matches.iter().map(|m| (m["program"].as_u64().unwrap(), m["args"][0].as_str().unwrap())).collect::<Vec<_>>(), vec![(0, "a"), (1, "c"), (2, "a")]);
+    assert_eq!(responses["3"]["result"]["num_programs"], 3);
+    let abstractions = responses["4"]["result"]["abstractions"].as_array().unwrap();
+    assert_eq!(abstractions[0]["body"], "(f (g #0) b)");
+    assert_eq!(responses["5"]["error"]["code"], -32601);
+    assert_eq!(responses["null"]["error"]["code"], -32700);
+
+    // the corpus is still loaded, and rewriting with the new abstraction agrees with compression
+    server.handle_line(&serde_json::json!({"jsonrpc": "2.0", "id": 6, "method": "rewrite", "params": {"abstractions": abstractions, "args": "--skip-invalid"}}).to_string());
+    server.wait();
+    assert_eq!(out.responses()["6"]["result"]["rewritten"], responses["4"]["result"]["rewritten"]);
+
+    // an abstraction with holes numbered past its arity makes rewriting panic, which is just an error of that request
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 7, "method": "rewrite", "params": {"abstractions": [{"name": "bad", "arity": 1, "body": "(f #1)"}], "args": "--skip-invalid"}}"#);
+    server.wait();
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 8, "method": "cancel", "params": {"id": 7}}"#);
+    let responses = out.responses();
+    assert_eq!(responses["7"]["error"]["code"], -32000);
+    assert!(responses["7"]["error"]["message"].as_str().unwrap().contains("panicked"));
+    assert_eq!(responses["8"]["result"]["cancelled"], false);
+}
+
+#[test]
+fn serve_cancel() {
+    let out = SharedBuf::default();
+    let mut server = Server::new(None, TreeConfig::default(), out.clone());
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 0, "method": "load", "params": {"file": "data/cogsci/nuts-bolts.json"}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": "slow", "method": "compress", "params": {"args": "-i10 -a3"}}"#);
+    // the id is taken until the first request finishes
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": "slow", "method": "compress", "params": {"args": "-i1 -a1"}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 1, "method": "cancel", "params": {"id": "slow"}}"#);
+    server.handle_line(r#"{"jsonrpc": "2.0", "id": 2, "method": "cancel", "params": {"id": "unknown"}}"#);
+    server.wait();
+
+    let responses = out.responses();
+    assert_eq!(responses["1"]["result"]["cancelled"], true);
+    assert_eq!(responses["2"]["result"]["cancelled"], false);
+    assert_eq!(responses["\"slow\""]["error"]["code"], -32800);
+    let slow_codes: Vec<Value> = out.all_responses().into_iter().filter(|response| response["id"] == "slow").map(|response| response["error"]["code"].clone()).collect();
+    assert_eq!(slow_codes, vec![-32600, -32800]);
+}
+
+#[test]
+fn pattern_matches_respect_binders() {
+    let programs: Vec<ExprOwned> = parse_programs(&["(lam (f $0 (g $1)))".to_string(), "(lam (f $0 (g $0)))".to_string()], None).0.into_iter().map(|(_, p)| p).collect();
+    let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+    let idx = set.parse_extend("(lam (f $0 #0))").unwrap();
+    let pattern = ExprOwned::new(set, idx);
+    let matches = find_matches(&pattern, &programs);
+    // $1 inside the pattern's lambda is $0 outside of it, and (g $0) refers to the pattern's own lambda
+    assert_eq!(matches.len(), 1);
+    assert_eq!((matches[0].program, matches[0].args[0].as_str()), (0, "(g $0)"));
+}
+/// records every event as a line of text
+#[derive(Default)]
+struct RecordingObserver(std::sync::Mutex<Vec<String>>);
+
+impl Observer for RecordingObserver {
+    fn prep_finished(&self, iteration: usize, _timings: &StepTimings) {
+        self.0.lock().unwrap().push(format!("prep {iteration}"));
+    }
+    fn new_best(&self, iteration: usize, utility: i32, abstraction: &str) {
+        self.0.lock().unwrap().push(format!("best {iteration} {utility} {abstraction}"));
+    }
+    fn worklist_stats(&self, iteration: usize, stats: &WorklistStats) {
+        assert_eq!(stats.worklist_steps % 10, 0);
+        self.0.lock().unwrap().push(format!("stats {iteration}"));
+    }
+    fn worklist_stats_interval(&self) -> usize {
+        10
+    }
+    fn iteration_finished(&self, iteration: usize, result: &CompressionStepResult) {
+        self.0.lock().unwrap().push(format!("done {iteration} {} {}", result.done.utility, result.inv.body));
+    }
+}
+
+#[test]
+fn observer_events() {
+    let input = InputFormat::ProgramsList.load_programs_and_tasks(Path::new("data/cogsci/nuts-bolts.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i2 -a2 -t2 --silent".split_whitespace());
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    let (step_results, _) = multistep_compression(&input.train_programs, None, None, None, None, None, Some(observer.clone()), &cfg).unwrap();
+    let events = observer.0.lock().unwrap().clone();
+
+    for (i, res) in step_results.iter().enumerate() {
+        let iteration_events: Vec<&String> = events.iter().filter(|e| e.split(' ').nth(1) == Some(&i.to_string())).collect();
+        assert_eq!(iteration_events.first().unwrap().as_str(), format!("prep {i}"));
+        assert!(iteration_events.iter().any(|e| e.starts_with("stats")));
+        // the last new best is the abstraction that gets chosen
+        let last_best = iteration_events.iter().rev().find(|e| e.starts_with("best")).unwrap();
+        assert_eq!(last_best.as_str(), format!("best {i} {} {}", res.done.utility, res.inv.body));
+        assert_eq!(iteration_events.last().unwrap().as_str(), format!("done {i} {} {}", res.done.utility, res.inv.body));
+    }
+}
+#[test]
+fn config_files_and_builder() {
+    let path = std::env::temp_dir().join(format!("stitch_config_{}.toml", std::process::id()));
+    std::fs::write(&path, "iterations = 5\n[step]\nmax_arity = 1\nhole_choice = \"breadth-first\"\nfused_lambda_tags = [2]\n[step.cost]\ncost_app = 3\n").unwrap();
+
+    // flags on the command line win over the file, which wins over the defaults
+    let cfg = MultistepCompressionConfig::from_args(format!("compress -a3 --config {} --cost-lam 7", path.display()).split_whitespace()).unwrap();
+    assert_eq!(MultistepCompressionConfig::from_file(&path).unwrap().step.max_arity, 1);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(cfg.iterations, 5);
+    assert_eq!(cfg.step.max_arity, 3);
+    assert!(matches!(cfg.step.hole_choice, HoleChoice::BreadthFirst));
+    assert_eq!(cfg.step.fused_lambda_tags.tags().unwrap().iter().copied().collect::<Vec<_>>(), vec![2]);
+    assert_eq!((cfg.step.cost.cost_lam, cfg.step.cost.cost_app, cfg.step.cost.cost_var), (7, 3, 100));
+    assert_eq!(cfg.step.threads, 1);
+
+    // the args of an output json can be read back in
+    let cfg2: MultistepCompressionConfig = serde_json::from_value(serde_json::json!(cfg)).unwrap();
+    assert_eq!(serde_json::json!(cfg2), serde_json::json!(cfg));
+    assert!(serde_json::from_value::<MultistepCompressionConfig>(serde_json::json!({"step": {"max_arty": 3}})).is_err());
+
+    // presets sit underneath both
+    let cfg = MultistepCompressionConfig::from_args("compress --preset fast -a3".split_whitespace()).unwrap();
+    assert_eq!((cfg.preset, cfg.step.max_arity, cfg.step.no_stats), (Some(Preset::Fast), 3, true));
+    assert!(MultistepCompressionConfig::from_args("compress --preset neurosymbolic".split_whitespace()).unwrap().validate().is_err());
+
+    let cfg = MultistepCompressionConfig::builder().iterations(4).max_arity(3).cost_app(2).build().unwrap();
+    assert_eq!((cfg.iterations, cfg.step.max_arity, cfg.step.cost.cost_app, cfg.step.cost.cost_lam), (4, 3, 2, 1));
+    assert!(MultistepCompressionConfig::builder().eta_long(true).build().is_err());
+    assert!(MultistepCompressionConfig::builder().eta_long(true).utility_by_rewrite(true).build().is_ok());
+    assert!(MultistepCompressionConfig::builder().threads(0).build().is_err());
+
+    // compression refuses an invalid config up front rather than panicking
+    let mut cfg = MultistepCompressionConfig::new();
+    cfg.step.eta_long = true;
+    let programs = vec!["(a b)".to_string(), "(a c)".to_string()];
+    assert!(matches!(multistep_compression(&programs, None, None, None, None, None, None, &cfg), Err(StitchError::InvalidInput(_))));
+}
+#[test]
 fn furniture_a2_i10() {
     compare_out_jsons("data/cogsci/furniture.json", "data/expected_outputs/furniture-a2-i10.json", "-i10 -a2 --rewrite-check", InputFormat::ProgramsList);
 }
@@ -199,18 +853,112 @@ fn origami_2_a3_i10() {
     compare_out_jsons("data/dc/origami/iteration_2_1.json", "data/expected_outputs/origami_2-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::Dreamcoder);
 }
 
+#[test]
+fn resume_nuts_bolts_a3_i10() {
+    compare_resumed_out_jsons("data/cogsci/nuts-bolts.json", "data/expected_outputs/nuts-bolts-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::ProgramsList);
+}
+
+#[test]
+fn resume_logo_iteration_1_a3_i10() {
+    compare_resumed_out_jsons("data/dc/logo_iteration_1.json", "data/expected_outputs/logo_iteration_1-a3-i10.json", "-i10 -a3 --rewrite-check", InputFormat::Dreamcoder);
+}
+
+#[test]
+fn resume_rejects_mismatched_checkpoints() {
+    let input = InputFormat::Dreamcoder.load_programs_and_tasks(Path::new("data/dc/logo_iteration_1.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i2 -a2".split_whitespace());
+    let first_step_cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --truncate 30".split_whitespace());
+    let checkpoint = Checkpoint::from_json(&run_compression(&input, &first_step_cfg)).unwrap();
+    assert!(checkpoint.reordered);
+    // the input's tasks belong to the untruncated programs
+    let res = multistep_compression_resume(&checkpoint, input.tasks.clone(), None, input.name_mapping.clone(), input.inventions.clone(), None, &cfg);
+    assert!(matches!(res, Err(StitchError::InvalidInput(message)) if message.contains("tasks and weights")));
+    // without tasks the truncated programs can still be resumed
+    assert!(multistep_compression_resume(&checkpoint, None, None, input.name_mapping.clone(), input.inventions.clone(), None, &cfg).is_ok());
+    let shuffled = MultistepCompressionConfig::parse_from("compress -i2 -a2 --shuffle".split_whitespace());
+    assert!(matches!(multistep_compression_resume(&checkpoint, None, None, None, None, None, &shuffled), Err(StitchError::InvalidInput(_))));
+
+    let no_programs_cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --no-json-programs".split_whitespace());
+    let err = Checkpoint::from_json(&run_compression(&input, &no_programs_cfg)).unwrap_err();
+    assert!(err.contains("--no-json-programs"));
+}
+
+#[test]
+fn resume_translates_rewritten_dreamcoder() {
+    let input = InputFormat::Dreamcoder.load_programs_and_tasks(Path::new("data/dc/logo_iteration_1.json")).unwrap();
+    let first_step_cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2".split_whitespace());
+    let checkpoint = Checkpoint::from_json(&run_compression(&input, &first_step_cfg)).unwrap();
+    assert!(checkpoint.rewritten_dreamcoder.is_none());
+    // nothing left to learn, so rewritten_dreamcoder has to come from the checkpoint's programs
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2 --rewritten-dreamcoder".split_whitespace());
+    let (step_results, output) = multistep_compression_resume(&checkpoint, input.tasks.clone(), None, input.name_mapping.clone(), input.inventions.clone(), None, &cfg).unwrap();
+    assert!(step_results.is_empty());
+    let rewritten_dreamcoder: Vec<&str> = output["rewritten_dreamcoder"].as_array().unwrap().iter().map(|p| p.as_str().unwrap()).collect();
+    assert_eq!(rewritten_dreamcoder.len(), checkpoint.rewritten.len());
+    assert!(rewritten_dreamcoder.iter().all(|p| !p.contains("fn_") && !p.contains("dreamcoder_abstraction_")));
+    let inv = checkpoint.abstractions[0]["dreamcoder"].as_str().unwrap();
+    assert!(rewritten_dreamcoder.iter().any(|p| p.contains(inv)));
+}
+
 #[test]
 fn neurosym_match_at_tag() {
     compare_out_jsons("data/neurosym/match_at_tag.json", "data/expected_outputs/neurosym_match_at_tag.json", "", InputFormat::ProgramsList);
     compare_out_jsons("data/neurosym/match_at_tag.json", "data/expected_outputs/neurosym_match_at_tag_excluded.json", "--fused-lambda-tags 2", InputFormat::ProgramsList);
 }
 
+#[test]
+fn preset_dreamcoder() {
+    compare_out_jsons("data/dc/origami/iteration_0_3.json", "data/expected_outputs/origami_0-preset-dreamcoder-i2.json", "--preset dreamcoder -i2 --rewrite-check", InputFormat::Dreamcoder);
+}
+
+#[test]
+fn preset_neurosymbolic() {
+    compare_out_jsons("data/neurosym/match_at_tag.json", "data/expected_outputs/neurosym_match_at_tag-preset-neurosymbolic.json", "--preset neurosymbolic --fused-lambda-tags 2 --rewrite-check", InputFormat::ProgramsList);
+}
+
+#[test]
+fn preset_fast() {
+    compare_out_jsons("data/cogsci/nuts-bolts.json", "data/expected_outputs/nuts-bolts-preset-fast-i3.json", "--preset fast -i3 --rewrite-check", InputFormat::ProgramsList);
+}
+
 #[test]
 fn neurosym_metavariable_with_tag() {
     compare_out_jsons("data/neurosym/metavariable_with_tag.json", "data/expected_outputs/neurosym_metavariable_with_tag.json", "", InputFormat::ProgramsList);
     compare_out_jsons("data/neurosym/metavariable_with_tag.json", "data/expected_outputs/neurosym_metavariable_with_tag_excluded.json", "--fused-lambda-tags 2", InputFormat::ProgramsList);
 }
 
+#[test]
+fn bench_suite_and_regressions() {
+    let suite = BenchSuite::load(Path::new("data/bench.toml")).unwrap();
+    assert!(suite.benchmark.iter().all(|b| b.file.exists()));
+    let nuts_bolts = suite.filtered(Some("nuts-bolts"));
+    assert_eq!(nuts_bolts.len(), 1);
+    assert_eq!(nuts_bolts[0].fmt, "programs-list");
+    assert_eq!(suite.filtered(None).len(), suite.benchmark.len());
+
+    let res = Benchmark { args: "-i2 -a2".to_string(), ..nuts_bolts[0].clone() }.run(2).unwrap();
+    assert_eq!((res.num_abstractions, res.runs), (2, 2));
+    assert!(res.compression_ratio > 1.);
+    // peak memory is only measured on Linux
+    if cfg!(target_os = "linux") {
+        assert!(res.peak_rss_kb.unwrap() > 0);
+    } else {
+        assert!(res.peak_rss_kb.is_none());
+    }
+
+    let report = |wall_millis, peak_rss_kb, compression_ratio| BenchReport { benchmarks: vec![BenchResult { wall_millis, peak_rss_kb, compression_ratio, ..res.clone() }] };
+    let thresholds = BenchThresholds::default();
+    let baseline = report(1000, Some(1000), 4.);
+    assert!(report(1200, Some(1200), 4.).regressions(&baseline, &thresholds).is_empty());
+    assert!(report(1000, None, 4.).regressions(&baseline, &thresholds).is_empty());
+    let regressions = report(1300, Some(1300), 3.9).regressions(&baseline, &thresholds);
+    assert_eq!(regressions.iter().map(|r| r.metric.as_str()).collect::<Vec<_>>(), ["wall_millis", "peak_rss_kb", "compression_ratio"]);
+    // small slowdowns of fast benchmarks are within the slack
+    assert!(report(30, Some(1000), 4.).regressions(&report(10, Some(1000), 4.), &thresholds).is_empty());
+    // benchmarks missing from the baseline aren't compared
+    assert!(report(1300, Some(1300), 3.9).regressions(&BenchReport::default(), &thresholds).is_empty());
+}
+
 // todo disabled bc nondeterminism with 2 equal things on the first invention (usually threading prevents that, but here for some reason you always get the same result when running from commandline and a diff result when running from test)
 // #[test]
 // fn origami_3_a3_i10() {
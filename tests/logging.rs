@@ -0,0 +1,27 @@
+//! The logger is global to the process, so logging gets a test binary of its own where it can't end up writing the logs
+//! of other tests to a file that gets deleted
+
+use std::path::Path;
+
+use stitch_core::*;
+use clap::Parser;
+use serde_json::Value;
+
+#[test]
+fn json_lines_logging() {
+    let path = std::env::temp_dir().join(format!("stitch_log_{}.jsonl", std::process::id()));
+    let log_cfg = LogConfig::parse_from(format!("log --log-level off --log-json {} --log-json-level info", path.display()).split_whitespace());
+    init_logging(&log_cfg).unwrap();
+    let input = InputFormat::ProgramsList.load_programs_and_tasks(Path::new("data/cogsci/nuts-bolts.json")).unwrap();
+    let cfg = MultistepCompressionConfig::parse_from("compress -i1 -a2".split_whitespace());
+    multistep_compression(&input.train_programs, None, None, None, None, None, None, &cfg).unwrap();
+    log::logger().flush();
+
+    let records: Vec<Value> = std::fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    std::fs::remove_file(&path).unwrap();
+    assert!(records.iter().any(|r| r["target"] == "stitch::prep" && r["message"].as_str().unwrap().starts_with("TOTAL PREP")));
+    assert!(records.iter().any(|r| r["target"] == "stitch::multistep" && r["message"].as_str().unwrap().contains("=======Iteration 0=======")));
+    // debug records like the per-phase timings are left out, and colors are stripped
+    assert!(records.iter().all(|r| r["level"] != "DEBUG"));
+    assert!(records.iter().all(|r| !r["message"].as_str().unwrap().contains('\u{1b}')));
+}
@@ -0,0 +1,386 @@
+//! Random closed, beta-normal corpora for the soundness tests, along with what's needed to check and shrink them:
+//! programs as plain trees that can be expanded by inlining abstractions, and cases that shrink to smaller ones.
+
+// each test binary that includes this uses a different part of it
+#![allow(dead_code)]
+
+use stitch_core::*;
+use rand::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// A program as a plain tree, which is easier to generate, shrink and expand than an `ExprSet`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Term {
+    Prim(String),
+    Var(i32),
+    IVar(i32),
+    App(Box<Term>, Box<Term>),
+    Lam(Box<Term>),
+}
+
+pub use Term::*;
+
+const PRIMS: [&str; 6] = ["a", "b", "c", "f", "g", "h"];
+
+impl Term {
+    pub fn parse(s: &str) -> Term {
+        let mut set = ExprSet::empty(Order::ChildFirst, false, false);
+        let idx = set.parse_extend(s).unwrap();
+        Term::of_expr(set.get(idx))
+    }
+
+    pub fn of_expr(e: Expr) -> Term {
+        match e.node() {
+            Node::Prim(p) => Prim(p.to_string()),
+            Node::Var(i, _) => Var(*i),
+            Node::IVar(i) => IVar(*i),
+            Node::App(f, x) => App(Box::new(Term::of_expr(e.get(*f))), Box::new(Term::of_expr(e.get(*x)))),
+            Node::Lam(b, _) => Lam(Box::new(Term::of_expr(e.get(*b)))),
+        }
+    }
+
+    /// add `amount` to every variable that's free at `depth` lambdas into this term
+    pub fn shift(&self, amount: i32, depth: i32) -> Term {
+        match self {
+            Var(i) if *i >= depth => Var(i + amount),
+            Prim(_) | Var(_) | IVar(_) => self.clone(),
+            App(f, x) => App(Box::new(f.shift(amount, depth)), Box::new(x.shift(amount, depth))),
+            Lam(b) => Lam(Box::new(b.shift(amount, depth + 1))),
+        }
+    }
+
+    /// how many lambdas have to be above this term for it to be closed
+    pub fn needs(&self, depth: i32) -> i32 {
+        match self {
+            Var(i) => (i - depth + 1).max(0),
+            Prim(_) | IVar(_) => 0,
+            App(f, x) => f.needs(depth).max(x.needs(depth)),
+            Lam(b) => b.needs(depth + 1),
+        }
+    }
+
+    /// whether the variable `i` that's free at the root of this term is used in it
+    pub fn uses_var(&self, i: i32, depth: i32) -> bool {
+        match self {
+            Var(j) => *j == i + depth,
+            Prim(_) | IVar(_) => false,
+            App(f, x) => f.uses_var(i, depth) || x.uses_var(i, depth),
+            Lam(b) => b.uses_var(i, depth + 1),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Prim(_) | Var(_) | IVar(_) => 1,
+            App(f, x) => 1 + f.size() + x.size(),
+            Lam(b) => 1 + b.size(),
+        }
+    }
+
+    /// the head of a curried application and its arguments, so (f x y) gives f and [x, y]
+    pub fn spine(&self) -> (&Term, Vec<&Term>) {
+        let mut args = vec![];
+        let mut head = self;
+        while let App(f, x) = head {
+            args.push(&**x);
+            head = f;
+        }
+        args.reverse();
+        (head, args)
+    }
+
+    /// an abstraction body with #i replaced by `args[i]`. `depth` is the number of lambdas of the body above this point,
+    /// which the free variables of the arguments have to be shifted past
+    pub fn instantiate(&self, args: &[Term], depth: i32) -> Term {
+        match self {
+            IVar(i) => args[*i as usize].shift(depth, 0),
+            Prim(_) | Var(_) => self.clone(),
+            App(f, x) => App(Box::new(f.instantiate(args, depth)), Box::new(x.instantiate(args, depth))),
+            Lam(b) => Lam(Box::new(b.instantiate(args, depth + 1))),
+        }
+    }
+
+    /// inline every use of an abstraction in `invs` (by name, with its arity and body). A body can use earlier
+    /// abstractions, even partially applied like (fn_0 a) where fn_0 takes two arguments, so after each inlining
+    /// the result is expanded again
+    pub fn expand(&self, invs: &FxHashMap<String, (usize, Term)>) -> Term {
+        let (head, args) = self.spine();
+        match head {
+            Prim(p) if invs.contains_key(p) && args.len() >= invs[p].0 => {
+                let (arity, body) = &invs[p];
+                let args: Vec<Term> = args.into_iter().cloned().collect();
+                let inlined = args[*arity..].iter().fold(body.instantiate(&args[..*arity], 0), |f, x| App(Box::new(f), Box::new(x.clone())));
+                inlined.expand(invs)
+            }
+            _ => {
+                let head = match head {
+                    Lam(b) => Lam(Box::new(b.expand(invs))),
+                    _ => head.clone(),
+                };
+                args.into_iter().fold(head, |f, x| App(Box::new(f), Box::new(x.expand(invs))))
+            }
+        }
+    }
+
+    /// paths (which child to take at each App or Lam) to every subterm, outermost first
+    pub fn paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = vec![vec![]];
+        let children: Vec<&Term> = match self {
+            App(f, x) => vec![f, x],
+            Lam(b) => vec![b],
+            _ => vec![],
+        };
+        for (i, child) in children.into_iter().enumerate() {
+            paths.extend(child.paths().into_iter().map(|mut path| { path.insert(0, i); path }));
+        }
+        paths
+    }
+
+    pub fn at(&self, path: &[usize]) -> &Term {
+        match (self, path) {
+            (_, []) => self,
+            (App(f, _), [0, rest @ ..]) | (Lam(f), [0, rest @ ..]) => f.at(rest),
+            (App(_, x), [1, rest @ ..]) => x.at(rest),
+            _ => unreachable!(),
+        }
+    }
+
+    /// the number of lambdas above the end of `path`
+    pub fn lams_on(&self, path: &[usize]) -> i32 {
+        match (self, path) {
+            (_, []) => 0,
+            (Lam(b), [_, rest @ ..]) => 1 + b.lams_on(rest),
+            (App(f, _), [0, rest @ ..]) => f.lams_on(rest),
+            (App(_, x), [_, rest @ ..]) => x.lams_on(rest),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn replace(&self, path: &[usize], new: Term) -> Term {
+        match (self, path) {
+            (_, []) => new,
+            (App(f, x), [0, rest @ ..]) => App(Box::new(f.replace(rest, new)), x.clone()),
+            (App(f, x), [1, rest @ ..]) => App(f.clone(), Box::new(x.replace(rest, new))),
+            (Lam(b), [0, rest @ ..]) => Lam(Box::new(b.replace(rest, new))),
+            _ => unreachable!(),
+        }
+    }
+
+    /// every occurrence of `old` replaced by `new`
+    pub fn replace_all(&self, old: &Term, new: &Term) -> Term {
+        match self {
+            _ if self == old => new.clone(),
+            App(f, x) => App(Box::new(f.replace_all(old, new)), Box::new(x.replace_all(old, new))),
+            Lam(b) => Lam(Box::new(b.replace_all(old, new))),
+            _ => self.clone(),
+        }
+    }
+
+    /// smaller terms to try in place of this one, which don't refer to any more lambdas than it does
+    pub fn smaller(&self) -> Vec<Term> {
+        let mut smaller = match self {
+            App(f, x) => vec![(**f).clone(), (**x).clone()],
+            // without the lambda, as long as the body doesn't use its variable
+            Lam(b) if !b.uses_var(0, 0) => vec![b.shift(-1, 0)],
+            _ => vec![],
+        };
+        if *self != Prim("a".to_string()) {
+            smaller.push(Prim("a".to_string()));
+        }
+        smaller
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prim(p) => write!(f, "{p}"),
+            Var(i) => write!(f, "${i}"),
+            IVar(i) => write!(f, "#{i}"),
+            Lam(b) => write!(f, "(lam {b})"),
+            App(_, _) => {
+                let (head, args) = self.spine();
+                write!(f, "({head}")?;
+                args.iter().try_for_each(|arg| write!(f, " {arg}"))?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Generates closed, beta-normal programs. Subterms are often reused, sometimes under more lambdas with their free
+/// variables shifted to match, so there's plenty for compression to find
+pub struct Generator {
+    pub rng: StdRng,
+    pub pool: Vec<Term>,
+}
+
+impl Generator {
+    /// a term of about `size` nodes under `env` lambdas
+    pub fn term(&mut self, env: i32, size: usize) -> Term {
+        let fits: Vec<&Term> = self.pool.iter().filter(|t| t.size() <= 2 * size && t.needs(0) <= env).collect();
+        if !fits.is_empty() && self.rng.gen_bool(0.3) {
+            let reused = (*fits.choose(&mut self.rng).unwrap()).clone();
+            let reused = reused.shift(self.rng.gen_range(0..=env - reused.needs(0)), 0);
+            if self.rng.gen_bool(0.5) {
+                return reused
+            }
+            // with one of its subterms swapped out, so the part they share has an argument
+            let path = reused.paths().choose(&mut self.rng).unwrap().clone();
+            let env = env + reused.lams_on(&path);
+            let in_head = path.last() == Some(&0) && matches!(reused.at(&path[..path.len() - 1]), App(_, _));
+            let fresh = if in_head { self.leaf(env) } else { self.term(env, 3) };
+            return reused.replace(&path, fresh)
+        }
+        let term = if size <= 1 || self.rng.gen_bool(0.15) {
+            self.leaf(env)
+        } else if self.rng.gen_bool(0.25) {
+            Lam(Box::new(self.term(env + 1, size - 1)))
+        } else {
+            // the head is never a lambda so programs stay beta-normal
+            let num_args = self.rng.gen_range(1..=3);
+            let head = self.leaf(env);
+            (0..num_args).fold(head, |f, _| {
+                let x = self.term(env, (size - 1) / num_args);
+                App(Box::new(f), Box::new(x))
+            })
+        };
+        if term.size() > 1 {
+            self.pool.push(term.clone());
+        }
+        term
+    }
+
+    pub fn leaf(&mut self, env: i32) -> Term {
+        if env > 0 && self.rng.gen_bool(0.5) {
+            Var(self.rng.gen_range(0..env))
+        } else {
+            Prim(PRIMS.choose(&mut self.rng).unwrap().to_string())
+        }
+    }
+}
+
+/// options to try besides the defaults, none of which should change whether compression is sound
+pub const FLAGS: [&str; 7] = [
+    "--no-curried-metavars",
+    "--no-curried-bodies",
+    "--no-opt-upper-bound",
+    "--no-opt-force-multiuse",
+    "--no-opt-useless-abstract",
+    "--no-opt-arity-zero",
+    "--inv-candidates 3",
+];
+
+/// A corpus and the options to compress it with
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub programs: Vec<Term>,
+    pub iterations: usize,
+    pub max_arity: usize,
+    pub flag: Option<&'static str>,
+}
+
+impl Case {
+    pub fn random(seed: u64) -> Case {
+        let mut gen = Generator { rng: StdRng::seed_from_u64(seed), pool: vec![] };
+        let num_programs = gen.rng.gen_range(2..=7);
+        let programs = (0..num_programs).map(|_| {
+            let size = gen.rng.gen_range(4..=30);
+            gen.term(0, size)
+        }).collect();
+        let flag = if gen.rng.gen_bool(0.5) { None } else { Some(*FLAGS.choose(&mut gen.rng).unwrap()) };
+        Case { programs, iterations: gen.rng.gen_range(1..=3), max_arity: gen.rng.gen_range(1..=3), flag }
+    }
+
+    pub fn args(&self) -> String {
+        format!("-i{} -a{} --rewrite-check {}", self.iterations, self.max_arity, self.flag.unwrap_or("")).trim().to_string()
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        let programs: Vec<String> = self.programs.iter().map(|p| p.to_string()).collect();
+        let cfg = MultistepCompressionConfig::from_args(format!("compress --silent --quiet {}", self.args()).split_whitespace()).unwrap();
+        // rewrite_check fails if rewrite_fast disagrees with the slow rewrite, and anything that still panics is a failure too
+        let step_results = match panic::catch_unwind(AssertUnwindSafe(|| multistep_compression(&programs, None, None, None, None, None, None, &cfg))) {
+            Ok(Ok((step_results, _))) => step_results,
+            Ok(Err(e)) => return Err(e.to_string()),
+            Err(payload) => {
+                let message = payload.downcast_ref::<String>().cloned().or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()));
+                return Err(format!("panicked: {}", message.unwrap_or_default()))
+            }
+        };
+
+        let cost_fn = cfg.step.cost.expr_cost();
+        let mut invs: FxHashMap<String, (usize, Term)> = FxHashMap::default();
+        for res in &step_results {
+            // every program is its own task, so the corpus cost is just the sum
+            let rewritten_cost: i32 = res.rewritten.iter().map(|p| p.cost(&cost_fn)).sum();
+            if res.expected_cost != res.final_cost || res.final_cost != rewritten_cost {
+                return Err(format!("{} has a utility of {} predicting a cost of {} but rewriting gave {} (rewritten programs cost {rewritten_cost})",
+                    res.inv.name, res.done.compressive_utility, res.expected_cost, res.final_cost))
+            }
+            invs.insert(res.inv.name.clone(), (res.inv.arity, Term::parse(&res.inv.body.to_string())));
+        }
+
+        if let Some(res) = step_results.last() {
+            for (original, rewritten) in self.programs.iter().zip(res.rewritten.iter()) {
+                let expanded = Term::parse(&rewritten.to_string()).expand(&invs);
+                if expanded != *original {
+                    return Err(format!("{rewritten} expands to {expanded} instead of {original}"))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// simpler versions of this case, most promising first
+    pub fn smaller(&self) -> Vec<Case> {
+        let mut smaller = vec![];
+        for i in 0..self.programs.len() {
+            if self.programs.len() > 1 {
+                let mut programs = self.programs.clone();
+                programs.remove(i);
+                smaller.push(Case { programs, ..self.clone() });
+            }
+        }
+        if self.iterations > 1 {
+            smaller.push(Case { iterations: self.iterations - 1, ..self.clone() });
+        }
+        if self.max_arity > 1 {
+            smaller.push(Case { max_arity: self.max_arity - 1, ..self.clone() });
+        }
+        if self.flag.is_some() {
+            smaller.push(Case { flag: None, ..self.clone() });
+        }
+        // a subterm shared between programs is usually what's being abstracted, so it's shrunk everywhere at once
+        let mut subterms: Vec<&Term> = self.programs.iter().flat_map(|p| p.paths().into_iter().map(move |path| p.at(&path))).collect();
+        let mut seen: FxHashSet<&Term> = FxHashSet::default();
+        subterms.retain(|t| seen.insert(*t));
+        for old in subterms {
+            for new in old.smaller() {
+                let programs = self.programs.iter().map(|p| p.replace_all(old, &new)).collect();
+                smaller.push(Case { programs, ..self.clone() });
+            }
+        }
+        for (i, program) in self.programs.iter().enumerate() {
+            for path in program.paths() {
+                for new in program.at(&path).smaller() {
+                    let mut programs = self.programs.clone();
+                    programs[i] = program.replace(&path, new);
+                    smaller.push(Case { programs, ..self.clone() });
+                }
+            }
+        }
+        smaller
+    }
+
+    /// greedily simplify this case for as long as `fails` still holds
+    pub fn shrink(self, fails: impl Fn(&Case) -> bool) -> Case {
+        let mut case = self;
+        while let Some(smaller) = case.smaller().into_iter().find(|smaller| fails(smaller)) {
+            case = smaller;
+        }
+        case
+    }
+}
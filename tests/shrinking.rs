@@ -0,0 +1,27 @@
+//! Tests of the inlining and shrinking that the soundness tests in `soundness.rs` rely on
+
+mod random_corpora;
+
+use random_corpora::*;
+use rustc_hash::FxHashMap;
+
+#[test]
+fn expand_and_shrink() {
+    // (fn_0 $0 b) with fn_0 = (lam (#0 (#1 $0))) under one lambda, so the free $0 of the argument is shifted into the body
+    let invs: FxHashMap<String, (usize, Term)> = vec![("fn_0".to_string(), (2, Term::parse("(lam (#0 (#1 $0)))")))].into_iter().collect();
+    assert_eq!(Term::parse("(lam (fn_0 $0 b))").expand(&invs), Term::parse("(lam (lam ($1 (b $0))))"));
+    assert_eq!(Term::parse("(f (fn_0 g c) a)").expand(&invs), Term::parse("(f (lam (g (c $0))) a)"));
+    // fn_1 is fn_0 partially applied, and only gets its second argument once it's inlined
+    let invs: FxHashMap<String, (usize, Term)> = vec![
+        ("fn_0".to_string(), (2, Term::parse("(#1 (#0 a))"))),
+        ("fn_1".to_string(), (0, Term::parse("(fn_0 b)"))),
+    ].into_iter().collect();
+    assert_eq!(Term::parse("(f (fn_1 c))").expand(&invs), Term::parse("(f (c (b a)))"));
+
+    // shrinks to the smallest closed corpus with a g under a lambda
+    let under_lambda = |t: &Term| t.paths().iter().any(|path| matches!(t.at(path), Lam(b) if b.to_string().contains('g')));
+    let case = (0..).map(Case::random).find(|case| case.programs.iter().any(under_lambda) && case.programs.len() > 2).unwrap();
+    let shrunk = case.shrink(|case| case.programs.iter().any(under_lambda));
+    assert_eq!(shrunk.programs, vec![Term::parse("(lam g)")]);
+    assert_eq!((shrunk.iterations, shrunk.max_arity, shrunk.flag), (1, 1, None));
+}
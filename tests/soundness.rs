@@ -0,0 +1,40 @@
+//! Property based soundness tests: compress random corpora with `--rewrite-check` (so `rewrite_fast` is checked against
+//! the slow rewrite for every finished pattern) and check that each abstraction's utility matches the cost of the
+//! rewritten corpus, and that inlining the abstractions back into the rewritten programs gives the original programs.
+//! A failing corpus is shrunk before it's reported. This is the only test in this binary, since it silences the panic
+//! messages of the whole process while it shrinks.
+//!
+//! Set STITCH_SOUNDNESS_CASES to run more (or fewer) corpora than the default 100, and STITCH_SOUNDNESS_SEED to start
+//! from another seed.
+
+mod random_corpora;
+
+use random_corpora::*;
+use std::panic;
+
+fn env_or<T: std::str::FromStr>(var: &str, default: T) -> T {
+    std::env::var(var).ok().and_then(|s| s.parse().ok()).unwrap_or(default)
+}
+
+#[test]
+fn random_corpora_compress_soundly() {
+    let num_cases: u64 = env_or("STITCH_SOUNDNESS_CASES", 100);
+    let first_seed: u64 = env_or("STITCH_SOUNDNESS_SEED", 0);
+
+    // shrinking panics a lot, so the messages are only shown for the final case
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for seed in first_seed..first_seed + num_cases {
+        let case = Case::random(seed);
+        assert!(case.programs.iter().all(|p| p.needs(0) == 0));
+        if let Err(e) = case.check() {
+            let shrunk = case.shrink(|case| case.check().is_err());
+            let shrunk_error = shrunk.check().unwrap_err();
+            panic::set_hook(hook);
+            let programs: Vec<String> = shrunk.programs.iter().map(|p| p.to_string()).collect();
+            panic!("seed {seed} failed: {e}\n\nshrunk to `compress {}` on {}\nwhich fails with: {shrunk_error}",
+                shrunk.args(), serde_json::to_string(&programs).unwrap());
+        }
+    }
+    panic::set_hook(hook);
+}